@@ -0,0 +1,60 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use satpass::orbital::{Ephemeris, Orbital};
+use satpass::tle::TLE;
+
+// The element set of the library tests
+const AQUA_TLE: &str = include_str!("../testdata/aqua_27424.tle");
+// 2013-10-30 12:00 UTC, the TLE epoch
+const START_UTC: f64 = 1383134400.0;
+const INTERVAL_SEC: f64 = 6.0 * 3600.0;
+
+fn targets() -> Vec<impl Fn(f64) -> Option<(f64, f64)>> {
+    // A storm drifting north-west through the western Pacific
+    (0..16)
+        .map(|k| {
+            let (lat0, lon0) = (5.0 + k as f64 * 2.0, 150.0 - k as f64 * 2.0);
+            move |t: f64| {
+                let hours = (t - START_UTC) / 3600.0;
+                Some((lat0 + 0.1 * hours, lon0 - 0.2 * hours))
+            }
+        })
+        .collect()
+}
+
+fn bench_passes(c: &mut Criterion) {
+    let mut lines = AQUA_TLE.lines();
+    let tle = TLE::new(lines.next().unwrap(), lines.next().unwrap()).unwrap();
+    let orbital = Orbital::new(&tle).unwrap();
+    let targets = targets();
+
+    c.bench_function("get_passes 16 targets", |b| {
+        b.iter(|| {
+            for position_at in targets.iter() {
+                black_box(orbital.get_passes(START_UTC, INTERVAL_SEC, position_at).unwrap());
+            }
+        })
+    });
+    c.bench_function("ephemeris 16 targets", |b| {
+        b.iter(|| {
+            let ephemeris =
+                Ephemeris::new(&orbital, START_UTC - 6300.0, START_UTC + INTERVAL_SEC + 180.0, 60.0);
+            for position_at in targets.iter() {
+                black_box(ephemeris.get_passes(START_UTC, INTERVAL_SEC, position_at).unwrap());
+            }
+        })
+    });
+    c.bench_function("storm_relative_track", |b| {
+        b.iter(|| {
+            black_box(
+                orbital
+                    .storm_relative_track(START_UTC, 600.0, 30.0, 140.0, 15.0, Some(300.0))
+                    .unwrap(),
+            )
+        })
+    });
+}
+
+criterion_group!(benches, bench_passes);
+criterion_main!(benches);
@@ -0,0 +1,92 @@
+//! Near-simultaneous passes of several satellites
+
+use crate::orbital::TCSatPassEvent;
+
+/// Passes of two satellites over the storm close in time
+#[derive(Debug, Clone)]
+pub struct CollocatedPair {
+    pub first_satellite: usize,
+    pub second_satellite: usize,
+    pub first: TCSatPassEvent,
+    pub second: TCSatPassEvent,
+    /// Second CPA time minus first CPA time, in seconds
+    pub time_offset: f64,
+    pub zenith_difference: f64,
+    pub distance_difference: f64,
+}
+
+/// Pair the events of every two satellites whose CPA times are within `window_sec`.
+/// `events[k]` holds the passes of satellite `k` that already cover the storm.
+pub fn match_passes(events: &[Vec<TCSatPassEvent>], window_sec: f64) -> Vec<CollocatedPair> {
+    let sorted: Vec<Vec<&TCSatPassEvent>> = events
+        .iter()
+        .map(|passes| {
+            let mut passes: Vec<&TCSatPassEvent> = passes.iter().collect();
+            passes.sort_by(|a, b| a.cpa_time.total_cmp(&b.cpa_time));
+            passes
+        })
+        .collect();
+    let mut pairs = Vec::new();
+    for first_satellite in 0..sorted.len() {
+        for second_satellite in first_satellite + 1..sorted.len() {
+            let seconds = &sorted[second_satellite];
+            // First pass of the second satellite not too early for the
+            // current pass of the first, which only moves forward
+            let mut window_start = 0;
+            for first in sorted[first_satellite].iter() {
+                while window_start < seconds.len() && seconds[window_start].cpa_time < first.cpa_time - window_sec {
+                    window_start += 1;
+                }
+                for second in seconds[window_start..].iter() {
+                    let time_offset = second.cpa_time - first.cpa_time;
+                    if time_offset > window_sec {
+                        break;
+                    }
+                    pairs.push(CollocatedPair {
+                        first_satellite,
+                        second_satellite,
+                        first: (*first).clone(),
+                        second: (*second).clone(),
+                        time_offset,
+                        zenith_difference: second.sat_zenith - first.sat_zenith,
+                        distance_difference: second.cpa_distance - first.cpa_distance,
+                    });
+                }
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.first.cpa_time.total_cmp(&b.first.cpa_time));
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orbital::fixtures;
+
+    fn event(cpa_time: f64, norad_id: u64) -> TCSatPassEvent {
+        TCSatPassEvent { norad_id, ..fixtures::tc_event(cpa_time) }
+    }
+
+    #[test]
+    fn test_match_passes() {
+        // Passes of three satellites over one storm, not in time order
+        let events = vec![
+            vec![event(10000.0, 1), event(0.0, 1)],
+            vec![event(10600.0, 2), event(-600.0, 2), event(600.5, 2), event(3000.0, 2)],
+            vec![event(10300.0, 3)],
+        ];
+        let pairs = match_passes(&events, 600.0);
+        let matched: Vec<(usize, usize, f64, f64)> = pairs
+            .iter()
+            .map(|pair| (pair.first_satellite, pair.second_satellite, pair.first.cpa_time, pair.time_offset))
+            .collect();
+        // Offsets of exactly the window match, anything beyond does not
+        assert_eq!(
+            matched,
+            vec![(0, 1, 0.0, -600.0), (0, 1, 10000.0, 600.0), (0, 2, 10000.0, 300.0), (1, 2, 10600.0, -300.0)]
+        );
+        assert!(pairs.iter().all(|pair| pair.first.norad_id == pair.first_satellite as u64 + 1
+            && pair.second.norad_id == pair.second_satellite as u64 + 1));
+    }
+}
@@ -0,0 +1,268 @@
+//! Defaults for command line options from a TOML file
+//!
+//! Top-level keys are long option names and set the default of that option
+//! in every command that takes it, so options given on the command line win.
+//! Lists give the values of repeatable options. Two keys are not options:
+//!
+//! - `tle-dirs`: directories searched for TLE files not found as given,
+//!   relative to the config file
+//! - `[sensors.NAME]`: a TLE file under a short name with options used
+//!   whenever the TLE file is given as `NAME`
+//!
+//! ```toml
+//! tle-dirs = ["~/data/tle"]
+//! intensity = 64
+//! format = "csv"
+//!
+//! [sensors.aqua]
+//! tle = "aqua.txt"
+//! product = ["MYD021KM", "MYD03"]
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use clap::Command;
+use toml::{Table, Value};
+
+/// Unusable config file
+#[derive(Debug)]
+pub enum ConfigError {
+    Read { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, source: toml::de::Error },
+    /// A key that is no option, or a value of the wrong type
+    Invalid { path: PathBuf, message: String },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read { path, source } => write!(f, "{}: {}", path.display(), source),
+            ConfigError::Parse { path, source } => write!(f, "{}: {}", path.display(), source),
+            ConfigError::Invalid { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(source),
+            ConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// Named TLE file with its own option defaults
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sensor {
+    pub tle: Option<String>,
+    options: Table,
+}
+
+/// Parsed config file
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    path: PathBuf,
+    options: Table,
+    tle_dirs: Vec<PathBuf>,
+    sensors: BTreeMap<String, Sensor>,
+}
+
+impl ConfigFile {
+    /// `$XDG_CONFIG_HOME/satpass/config.toml`, by default under `~/.config`
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home_dir().map(|home| home.join(".config")))?;
+        Some(config_home.join("satpass").join("config.toml"))
+    }
+
+    /// The file at the default path, if there is one
+    pub fn load_default() -> Result<Option<Self>, ConfigError> {
+        match Self::default_path() {
+            Some(path) if path.is_file() => Self::load(&path).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|source| ConfigError::Read { path: path.to_path_buf(), source })?;
+        Self::parse(path, &text)
+    }
+
+    /// Parse `text` read from `path`, which relative TLE directories are
+    /// taken from
+    pub fn parse(path: &Path, text: &str) -> Result<Self, ConfigError> {
+        let mut options: Table =
+            toml::from_str(text).map_err(|source| ConfigError::Parse { path: path.to_path_buf(), source })?;
+        let invalid = |message: String| ConfigError::Invalid { path: path.to_path_buf(), message };
+        let base = path.parent().unwrap_or(Path::new(""));
+        let tle_dirs = match options.remove("tle-dirs") {
+            None => Vec::new(),
+            Some(value) => strings(&value)
+                .ok_or_else(|| invalid("tle-dirs must be a list of directories".to_string()))?
+                .iter()
+                .map(|dir| base.join(expand_home(dir)))
+                .collect(),
+        };
+        let mut sensors = BTreeMap::new();
+        if let Some(value) = options.remove("sensors") {
+            let Value::Table(table) = value else {
+                return Err(invalid("sensors must be a table of [sensors.NAME] tables".to_string()));
+            };
+            for (name, value) in table {
+                let Value::Table(mut options) = value else {
+                    return Err(invalid(format!("sensors.{} must be a table", name)));
+                };
+                let tle = match options.remove("tle") {
+                    None => None,
+                    Some(Value::String(tle)) => Some(tle),
+                    Some(_) => return Err(invalid(format!("sensors.{}.tle must be a file name", name))),
+                };
+                sensors.insert(name, Sensor { tle, options });
+            }
+        }
+        Ok(ConfigFile { path: path.to_path_buf(), options, tle_dirs, sensors })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn sensor(&self, name: &str) -> Option<&Sensor> {
+        self.sensors.get(name)
+    }
+
+    /// `command` with the file values, then those of `sensor`, as option
+    /// defaults
+    pub fn apply(&self, command: Command, sensor: Option<&str>) -> Result<Command, ConfigError> {
+        let command = self.apply_table(command, &self.options, "")?;
+        match sensor.and_then(|name| self.sensors.get(name).map(|sensor| (name, sensor))) {
+            Some((name, sensor)) => self.apply_table(command, &sensor.options, &format!("sensors.{}.", name)),
+            None => Ok(command),
+        }
+    }
+
+    /// Path of a TLE file given on the command line: as given if it exists,
+    /// else the file of the sensor of that name, else the first match in the
+    /// TLE directories
+    pub fn resolve_tle(&self, path: &str) -> String {
+        if Path::new(path).exists() {
+            return path.to_string();
+        }
+        if let Some(tle) = self.sensors.get(path).and_then(|sensor| sensor.tle.as_deref())
+            && tle != path
+        {
+            return self.resolve_tle(&expand_home(tle).to_string_lossy());
+        }
+        self.tle_dirs
+            .iter()
+            .map(|dir| dir.join(path))
+            .find(|candidate| candidate.is_file())
+            .map_or_else(|| path.to_string(), |found| found.to_string_lossy().into_owned())
+    }
+
+    fn apply_table(&self, mut command: Command, table: &Table, prefix: &str) -> Result<Command, ConfigError> {
+        for (key, value) in table {
+            let values = strings(value).ok_or_else(|| ConfigError::Invalid {
+                path: self.path.clone(),
+                message: format!("{}{} must be a string, number, boolean or list of them", prefix, key),
+            })?;
+            let long = key.replace('_', "-");
+            let found;
+            (command, found) = set_default(command, &long, &values);
+            if !found {
+                return Err(ConfigError::Invalid {
+                    path: self.path.clone(),
+                    message: format!("unknown option '{}{}'", prefix, key),
+                });
+            }
+        }
+        Ok(command)
+    }
+}
+
+// Sets the default of option --`long` in `command` and its subcommands,
+// returning whether any command has it
+fn set_default(mut command: Command, long: &str, values: &[String]) -> (Command, bool) {
+    let id = command
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(long) && !arg.is_global_set())
+        .map(|arg| arg.get_id().clone());
+    let mut found = id.is_some();
+    if let Some(id) = id {
+        command = command.mut_arg(id, |arg| arg.default_values(values.to_vec()));
+    }
+    let names: Vec<String> = command.get_subcommands().map(|sub| sub.get_name().to_string()).collect();
+    for name in names {
+        command = command.mut_subcommand(name, |sub| {
+            let (sub, sub_found) = set_default(sub, long, values);
+            found |= sub_found;
+            sub
+        });
+    }
+    (command, found)
+}
+
+// Option values as written on the command line
+fn strings(value: &Value) -> Option<Vec<String>> {
+    match value {
+        Value::String(text) => Some(vec![text.clone()]),
+        Value::Integer(number) => Some(vec![number.to_string()]),
+        Value::Float(number) => Some(vec![number.to_string()]),
+        Value::Boolean(flag) => Some(vec![flag.to_string()]),
+        Value::Array(items) => items.iter().map(|item| strings(item)?.pop()).collect(),
+        Value::Datetime(_) | Value::Table(_) => None,
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").filter(|home| !home.is_empty()).map(PathBuf::from)
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction};
+
+    #[test]
+    fn test_apply() {
+        let command = Command::new("satpass")
+            .arg(Arg::new("distance").long("distance").default_value("1165"))
+            .arg(Arg::new("product").long("product").action(ArgAction::Append))
+            .subcommand(Command::new("sites").arg(Arg::new("distance").long("distance").default_value("1165")));
+        let text = "distance = 800\nproduct = [\"MYD021KM\", \"MYD03\"]\n\
+                    [sensors.npp]\ntle = \"npp.txt\"\nproduct = \"VNP02MOD\"\n";
+        let config = ConfigFile::parse(Path::new("/etc/satpass.toml"), text).unwrap();
+        let sensor = config.sensor("npp").unwrap();
+        assert_eq!(sensor.tle.as_deref(), Some("npp.txt"));
+
+        let command = config.apply(command, Some("npp")).unwrap();
+        let matches = command.clone().get_matches_from(["satpass"]);
+        assert_eq!(matches.get_one::<String>("distance").unwrap(), "800");
+        let products: Vec<&String> = matches.get_many("product").unwrap().collect();
+        assert_eq!(products, ["VNP02MOD"]);
+        // The command line overrides the file
+        let matches = command.clone().get_matches_from(["satpass", "sites", "--distance", "500"]);
+        let (_, sites) = matches.subcommand().unwrap();
+        assert_eq!(sites.get_one::<String>("distance").unwrap(), "500");
+        let matches = command.get_matches_from(["satpass", "sites"]);
+        let (_, sites) = matches.subcommand().unwrap();
+        assert_eq!(sites.get_one::<String>("distance").unwrap(), "800");
+
+        let config = ConfigFile::parse(Path::new("c.toml"), "swath = 1").unwrap();
+        let err = config.apply(Command::new("satpass"), None).unwrap_err();
+        assert_eq!(err.to_string(), "c.toml: unknown option 'swath'");
+    }
+}
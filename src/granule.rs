@@ -0,0 +1,481 @@
+//! Granule file names of satellite products
+//!
+//! A product is cut into granules of fixed duration aligned to 00:00 UTC, or
+//! into whole or part orbits, and names each granule with a template.
+//! Templates are literal text with fields in braces:
+//!
+//! - `{start:FORMAT}`, `{end:FORMAT}`: granule start or end time in chrono
+//!   strftime format, e.g. `{start:%Y%j.%H%M}`
+//! - `{granule}`: granule number within the UTC day, or within the orbit for
+//!   orbit products, counted from 1; `{granule:03}` pads it with zeros to
+//!   three digits
+//! - `{orbit}`: revolution number of the granule, `{orbit:06}` padded
+//! - `{node}`: `A` or `D` for the ascending or descending node
+//!
+//! Geostationary scans have no orbit or node, and leave those fields blank.
+//!
+//! `{{` and `}}` write literal braces. Names identify a granule and leave out
+//! collection, version and production time, so they match the start of the
+//! archived file names.
+
+use chrono::format::{Item, StrftimeItems};
+
+use crate::orbital::{OrbitNode, Revolution, ScanArea, TCSatPassEvent};
+use crate::output::dt_from_unix_seconds;
+
+/// Invalid granule name template
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateError {
+    /// A `{` without its closing `}`, or a `}` without its opening `{`
+    Unbalanced,
+    /// The field name is not one of start, end, granule, orbit or node
+    UnknownField(String),
+    /// The time format or number width of a field cannot be used
+    InvalidFormat(String),
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateError::Unbalanced => write!(f, "unbalanced braces, write {{{{ or }}}} for literal ones"),
+            TemplateError::UnknownField(name) => {
+                write!(f, "unknown field '{}', expected start, end, granule, orbit or node", name)
+            }
+            TemplateError::InvalidFormat(field) => write!(f, "invalid format in '{{{}}}'", field),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Text(String),
+    Start(String),
+    End(String),
+    /// Zero-padded to the width
+    Granule(usize),
+    Orbit(usize),
+    Node,
+}
+
+/// Parsed granule name template
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    fields: Vec<Field>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        let mut fields = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        spec.push(c);
+                    }
+                    if !closed {
+                        return Err(TemplateError::Unbalanced);
+                    }
+                    if !text.is_empty() {
+                        fields.push(Field::Text(std::mem::take(&mut text)));
+                    }
+                    fields.push(Self::parse_field(&spec)?);
+                }
+                '}' => return Err(TemplateError::Unbalanced),
+                _ => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            fields.push(Field::Text(text));
+        }
+        Ok(Template { fields })
+    }
+
+    fn parse_field(spec: &str) -> Result<Field, TemplateError> {
+        let (name, format) = spec.split_once(':').unwrap_or((spec, ""));
+        let invalid = || TemplateError::InvalidFormat(spec.to_string());
+        match name {
+            "start" | "end" => {
+                let format = if format.is_empty() { "%Y%m%dT%H%M%S" } else { format };
+                if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                    return Err(invalid());
+                }
+                Ok(if name == "start" {
+                    Field::Start(format.to_string())
+                } else {
+                    Field::End(format.to_string())
+                })
+            }
+            "granule" | "orbit" => {
+                let width = match format {
+                    "" => 0,
+                    _ if format.bytes().all(|b| b.is_ascii_digit()) => format.parse().map_err(|_| invalid())?,
+                    _ => return Err(invalid()),
+                };
+                Ok(if name == "granule" { Field::Granule(width) } else { Field::Orbit(width) })
+            }
+            "node" if format.is_empty() => Ok(Field::Node),
+            "node" => Err(invalid()),
+            _ => Err(TemplateError::UnknownField(name.to_string())),
+        }
+    }
+
+    /// Name of `granule`
+    pub fn render(&self, granule: &Granule) -> String {
+        self.fields
+            .iter()
+            .map(|field| match field {
+                Field::Text(text) => text.clone(),
+                Field::Start(format) => dt_from_unix_seconds(granule.start).format(format).to_string(),
+                Field::End(format) => dt_from_unix_seconds(granule.end).format(format).to_string(),
+                Field::Granule(width) => format!("{:0width$}", granule.number, width = *width),
+                Field::Orbit(width) => {
+                    granule.orbit.map_or_else(String::new, |orbit| format!("{:0width$}", orbit, width = *width))
+                }
+                Field::Node => match granule.node {
+                    Some(OrbitNode::Ascending) => "A".to_string(),
+                    Some(OrbitNode::Descending) => "D".to_string(),
+                    None => String::new(),
+                },
+            })
+            .collect()
+    }
+}
+
+/// One granule of a product
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Granule {
+    /// Start and end, unix seconds
+    pub start: f64,
+    pub end: f64,
+    /// Number within the UTC day of the start, or within the orbit, counted from 1
+    pub number: u64,
+    /// Revolution number of the observing orbit, None for geostationary scans
+    pub orbit: Option<u64>,
+    pub node: Option<OrbitNode>,
+}
+
+/// How a product is cut into granules
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GranuleSpan {
+    /// Fixed length, the first granule of each day starting `offset_sec`
+    /// after 00:00 UTC
+    Duration { duration_sec: f64, offset_sec: f64 },
+    /// Orbits split into `parts` equal pieces, starting where the argument of
+    /// latitude is `start_deg`: 0 at the ascending node, 90 at the northernmost
+    /// and 270 at the southernmost point. An orbit is numbered by the
+    /// revolution whose ascending node it contains.
+    Orbit { start_deg: f64, parts: u32 },
+}
+
+const MODIS: GranuleSpan = GranuleSpan::Duration { duration_sec: 300.0, offset_sec: 0.0 };
+const SIX_MINUTES: GranuleSpan = GranuleSpan::Duration { duration_sec: 360.0, offset_sec: 0.0 };
+const AHI_FULL_DISK: GranuleSpan = GranuleSpan::Duration { duration_sec: 600.0, offset_sec: 0.0 };
+// GPM processing cuts every constellation orbit at the southernmost point
+const GPM_ORBIT: GranuleSpan = GranuleSpan::Orbit { start_deg: 270.0, parts: 1 };
+
+// Archive directories: URL template and default collection
+const LAADS: &str = "https://ladsweb.modaps.eosdis.nasa.gov/archive/allData/{collection}/{product}/{start:%Y}/{start:%j}/";
+const MODIS_C61: Option<(&str, &str)> = Some((LAADS, "61"));
+const VIIRS_V2: Option<(&str, &str)> = Some((LAADS, "5200"));
+const GES_DISC_ATMS: Option<(&str, &str)> = Some((
+    "https://sounder.gesdisc.eosdis.nasa.gov/data/SNPP_Sounder_Level1/SNPPATMSL1B.{collection}/{start:%Y}/{start:%j}/",
+    "3",
+));
+// PPS keeps the version in the file name only
+const PPS_1C: Option<(&str, &str)> =
+    Some(("https://arthurhou.pps.eosdis.nasa.gov/gpmdata/{start:%Y}/{start:%m}/{start:%d}/1C/", ""));
+
+// Built-in products: name, template, granule span and archive
+type Preset = (&'static str, &'static str, GranuleSpan, Option<(&'static str, &'static str)>);
+
+const PRESETS: &[Preset] = &[
+    // MODIS L1B radiances, geolocation and L2 cloud products
+    ("MOD021KM", "MOD021KM.A{start:%Y%j.%H%M}", MODIS, MODIS_C61),
+    ("MYD021KM", "MYD021KM.A{start:%Y%j.%H%M}", MODIS, MODIS_C61),
+    ("MOD02HKM", "MOD02HKM.A{start:%Y%j.%H%M}", MODIS, MODIS_C61),
+    ("MYD02HKM", "MYD02HKM.A{start:%Y%j.%H%M}", MODIS, MODIS_C61),
+    ("MOD02QKM", "MOD02QKM.A{start:%Y%j.%H%M}", MODIS, MODIS_C61),
+    ("MYD02QKM", "MYD02QKM.A{start:%Y%j.%H%M}", MODIS, MODIS_C61),
+    ("MOD03", "MOD03.A{start:%Y%j.%H%M}", MODIS, MODIS_C61),
+    ("MYD03", "MYD03.A{start:%Y%j.%H%M}", MODIS, MODIS_C61),
+    ("MOD06_L2", "MOD06_L2.A{start:%Y%j.%H%M}", MODIS, MODIS_C61),
+    ("MYD06_L2", "MYD06_L2.A{start:%Y%j.%H%M}", MODIS, MODIS_C61),
+    ("MOD35_L2", "MOD35_L2.A{start:%Y%j.%H%M}", MODIS, MODIS_C61),
+    ("MYD35_L2", "MYD35_L2.A{start:%Y%j.%H%M}", MODIS, MODIS_C61),
+    // VIIRS L1B radiances and geolocation of Suomi NPP, NOAA-20 and NOAA-21
+    ("VNP02MOD", "VNP02MOD.A{start:%Y%j.%H%M}", SIX_MINUTES, VIIRS_V2),
+    ("VNP03MOD", "VNP03MOD.A{start:%Y%j.%H%M}", SIX_MINUTES, VIIRS_V2),
+    ("VNP02IMG", "VNP02IMG.A{start:%Y%j.%H%M}", SIX_MINUTES, VIIRS_V2),
+    ("VJ102MOD", "VJ102MOD.A{start:%Y%j.%H%M}", SIX_MINUTES, VIIRS_V2),
+    ("VJ103MOD", "VJ103MOD.A{start:%Y%j.%H%M}", SIX_MINUTES, VIIRS_V2),
+    ("VJ102IMG", "VJ102IMG.A{start:%Y%j.%H%M}", SIX_MINUTES, VIIRS_V2),
+    ("VJ202MOD", "VJ202MOD.A{start:%Y%j.%H%M}", SIX_MINUTES, VIIRS_V2),
+    ("VJ203MOD", "VJ203MOD.A{start:%Y%j.%H%M}", SIX_MINUTES, VIIRS_V2),
+    // ATMS L1B brightness temperatures from the NASA sounder SIPS. These are
+    // not the NOAA ATMS SDR (SATMS_*) files, whose 32 s granules are cut from
+    // a satellite base time rather than 00:00 UTC.
+    ("SNDR.SNPP.ATMS.L1B", "SNDR.SNPP.ATMS.{start:%Y%m%dT%H%M}.m06.g{granule:03}.L1B", SIX_MINUTES, GES_DISC_ATMS),
+    ("SNDR.J1.ATMS.L1B", "SNDR.J1.ATMS.{start:%Y%m%dT%H%M}.m06.g{granule:03}.L1B", SIX_MINUTES, None),
+    // Himawari AHI full disk, band 13
+    ("AHI-H08", "HS_H08_{start:%Y%m%d_%H%M}_B13_FLDK", AHI_FULL_DISK, None),
+    ("AHI-H09", "HS_H09_{start:%Y%m%d_%H%M}_B13_FLDK", AHI_FULL_DISK, None),
+    // GPM constellation L1C brightness temperatures, one file per orbit
+    (
+        "1C.GPM.GMI",
+        "1C.GPM.GMI.XCAL2016-C.{start:%Y%m%d}-S{start:%H%M%S}-E{end:%H%M%S}.{orbit:06}",
+        GPM_ORBIT,
+        PPS_1C,
+    ),
+    (
+        "1C.GCOMW1.AMSR2",
+        "1C.GCOMW1.AMSR2.XCAL2016-V.{start:%Y%m%d}-S{start:%H%M%S}-E{end:%H%M%S}.{orbit:06}",
+        GPM_ORBIT,
+        PPS_1C,
+    ),
+    (
+        "1C.F17.SSMIS",
+        "1C.F17.SSMIS.XCAL2021-V.{start:%Y%m%d}-S{start:%H%M%S}-E{end:%H%M%S}.{orbit:06}",
+        GPM_ORBIT,
+        PPS_1C,
+    ),
+    (
+        "1C.F18.SSMIS",
+        "1C.F18.SSMIS.XCAL2021-V.{start:%Y%m%d}-S{start:%H%M%S}-E{end:%H%M%S}.{orbit:06}",
+        GPM_ORBIT,
+        PPS_1C,
+    ),
+];
+
+/// Archive directory holding the granules of a product
+#[derive(Debug, Clone, PartialEq)]
+pub struct Archive {
+    url: String,
+    collection: String,
+}
+
+impl Archive {
+    /// `url` is a directory template taking the granule fields plus
+    /// `{product}` and `{collection}`
+    pub fn new(url: &str, collection: &str) -> Result<Self, TemplateError> {
+        let archive = Archive { url: url.to_string(), collection: collection.to_string() };
+        archive.template("")?;
+        Ok(archive)
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn collection(&self) -> &str {
+        &self.collection
+    }
+
+    /// Same archive with another collection or version
+    pub fn with_collection(&self, collection: &str) -> Result<Self, TemplateError> {
+        Archive::new(&self.url, collection)
+    }
+
+    /// Directory URL of `granule` of `product`
+    pub fn directory(&self, product: &str, granule: &Granule) -> String {
+        self.template(product).expect("archive template checked by Archive::new").render(granule)
+    }
+
+    fn template(&self, product: &str) -> Result<Template, TemplateError> {
+        // Braces in the substituted values are literal
+        let literal = |value: &str| value.replace('{', "{{").replace('}', "}}");
+        Template::parse(
+            &self
+                .url
+                .replace("{collection}", &literal(&self.collection))
+                .replace("{product}", &literal(product)),
+        )
+    }
+}
+
+/// Product with its granule naming
+#[derive(Debug, Clone)]
+pub struct Product {
+    pub name: String,
+    pub template: Template,
+    pub span: GranuleSpan,
+    /// Where the granules can be downloaded, if known
+    pub archive: Option<Archive>,
+}
+
+impl Product {
+    pub fn new(name: &str, template: &str, span: GranuleSpan) -> Result<Self, TemplateError> {
+        Ok(Product {
+            name: name.to_string(),
+            template: Template::parse(template)?,
+            span,
+            archive: None,
+        })
+    }
+
+    /// Built-in product by name, ignoring case
+    pub fn preset(name: &str) -> Option<Self> {
+        PRESETS
+            .iter()
+            .find(|(preset, _, _, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(preset, template, span, archive)| Product {
+                archive: archive
+                    .map(|(url, collection)| Archive::new(url, collection).expect("valid preset archive")),
+                ..Product::new(preset, template, *span).expect("valid preset template")
+            })
+    }
+
+    /// Names of the built-in products
+    pub fn preset_names() -> Vec<&'static str> {
+        PRESETS.iter().map(|(name, _, _, _)| *name).collect()
+    }
+
+    /// Granule containing `time`, observed on `revolution` and `node`. Orbit
+    /// products take the node from the part of the orbit instead, and have no
+    /// granule without a revolution.
+    pub fn granule_at(&self, time: f64, revolution: Option<&Revolution>, node: Option<OrbitNode>) -> Option<Granule> {
+        match self.span {
+            GranuleSpan::Duration { duration_sec, offset_sec } => {
+                let day_start = (time / 86400.0).floor() * 86400.0;
+                let index = ((time - day_start - offset_sec) / duration_sec).floor();
+                let start = day_start + offset_sec + index * duration_sec;
+                Some(Granule {
+                    start,
+                    end: start + duration_sec,
+                    number: (index + 1.0).max(1.0) as u64,
+                    orbit: revolution.map(|revolution| revolution.number),
+                    node,
+                })
+            }
+            GranuleSpan::Orbit { start_deg, parts } => {
+                let revolution = revolution?;
+                // Angles along the orbit from the ascending node of `revolution`
+                let part_deg = 360.0 / parts as f64;
+                let angle = (time - revolution.ascending_node) / revolution.period * 360.0;
+                let index = ((angle - start_deg) / part_deg).floor();
+                let first_deg = start_deg + index * part_deg;
+                let middle_deg = first_deg + part_deg / 2.0;
+                let orbit = revolution.number as f64
+                    + middle_deg / 360.0
+                    + (360.0 - start_deg).rem_euclid(360.0) / 360.0;
+                let start = revolution.ascending_node + first_deg / 360.0 * revolution.period;
+                Some(Granule {
+                    start,
+                    end: start + revolution.period / parts as f64,
+                    number: (index as i64).rem_euclid(parts as i64) as u64 + 1,
+                    orbit: Some(orbit.floor().max(0.0) as u64),
+                    node: Some(if (90.0..270.0).contains(&middle_deg.rem_euclid(360.0)) {
+                        OrbitNode::Descending
+                    } else {
+                        OrbitNode::Ascending
+                    }),
+                })
+            }
+        }
+    }
+
+    /// Granules observing `event`: those overlapping the time the swath
+    /// covers the storm footprint if it was found, otherwise the granule at
+    /// the scan start of geostationary scans or at CPA. Products are of the
+    /// full frame, so target-area scans have none.
+    pub fn granules(&self, event: &TCSatPassEvent) -> Vec<Granule> {
+        if event.scan_area == Some(ScanArea::Sector) {
+            return Vec::new();
+        }
+        let revolution = event.revolution();
+        let (start, end) = match (event.footprint_start, event.footprint_end) {
+            (Some(start), Some(end)) => (start, end),
+            _ => {
+                let time = event.scan_start.unwrap_or(event.cpa_time);
+                (time, time)
+            }
+        };
+        let first = self.granule_at(start, revolution.as_ref(), event.node);
+        std::iter::successors(first, |granule| {
+            // Just past the end so rounding cannot return the same granule
+            (granule.end < end).then(|| self.granule_at(granule.end + 1e-3, revolution.as_ref(), event.node))?
+        })
+        .collect()
+    }
+
+    /// Names of the granules observing `event`
+    pub fn granule_names(&self, event: &TCSatPassEvent) -> Vec<String> {
+        self.granules(event).iter().map(|granule| self.template.render(granule)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        // 2013-11-05 16:03:43 UTC
+        let time = 1383667423.0;
+        let revolution = Revolution { number: 60090, ascending_node: time - 2791.0, period: 5929.6 };
+        let name = |product: &str| {
+            let product = Product::preset(product).unwrap();
+            product.template.render(&product.granule_at(time, Some(&revolution), Some(OrbitNode::Descending)).unwrap())
+        };
+        assert_eq!(name("myd021km"), "MYD021KM.A2013309.1600");
+        assert_eq!(name("SNDR.SNPP.ATMS.L1B"), "SNDR.SNPP.ATMS.20131105T1600.m06.g161.L1B");
+        // The orbit starts a quarter period before the ascending node at 15:17:12
+        assert_eq!(
+            name("1C.GPM.GMI"),
+            "1C.GPM.GMI.XCAL2016-C.20131105-S145229-E163119.060090"
+        );
+    }
+
+    #[test]
+    fn test_orbit_granule_at() {
+        let revolution = Revolution { number: 100, ascending_node: 0.0, period: 6000.0 };
+        let granule = |start_deg: f64, time: f64| {
+            let product = Product::new("x", "x", GranuleSpan::Orbit { start_deg, parts: 4 }).unwrap();
+            let granule = product.granule_at(time, Some(&revolution), None).unwrap();
+            (granule.start, granule.end, granule.number, granule.orbit.unwrap(), granule.node.unwrap())
+        };
+        // Orbits starting at the ascending node
+        assert_eq!(granule(0.0, -10.0), (-1500.0, 0.0, 4, 99, OrbitNode::Ascending));
+        assert_eq!(granule(0.0, 10.0), (0.0, 1500.0, 1, 100, OrbitNode::Ascending));
+        // Orbits starting a quarter period before the ascending node
+        assert_eq!(granule(270.0, 4490.0), (3000.0, 4500.0, 4, 100, OrbitNode::Descending));
+        assert_eq!(granule(270.0, 4510.0), (4500.0, 6000.0, 1, 101, OrbitNode::Ascending));
+        assert_eq!(granule(270.0, 10.0), (0.0, 1500.0, 2, 100, OrbitNode::Ascending));
+        let product = Product::new("x", "x", GranuleSpan::Orbit { start_deg: 0.0, parts: 4 }).unwrap();
+        assert!(product.granule_at(10.0, None, Some(OrbitNode::Ascending)).is_none());
+    }
+
+    #[test]
+    fn test_template() {
+        let granule = Granule {
+            start: 1383667200.0,
+            end: 1383667560.0,
+            number: 161,
+            orbit: Some(10521),
+            node: Some(OrbitNode::Ascending),
+        };
+        let template = Template::parse("{{x}}_{start:%H%M}-{end:%H%M}_b{orbit:06}{node}_{granule}").unwrap();
+        assert_eq!(template.render(&granule), "{x}_1600-1606_b010521A_161");
+        assert_eq!(Template::parse("a{start"), Err(TemplateError::Unbalanced));
+        assert_eq!(Template::parse("a}"), Err(TemplateError::Unbalanced));
+        assert_eq!(Template::parse("{day}"), Err(TemplateError::UnknownField("day".to_string())));
+        assert_eq!(Template::parse("{orbit:x}"), Err(TemplateError::InvalidFormat("orbit:x".to_string())));
+    }
+}
@@ -0,0 +1,166 @@
+//! Summaries of the inputs: the element sets of a TLE file and the fixes of
+//! a track
+
+use geographiclib_rs::{Geodesic, InverseGeodesic};
+use serde::Serialize;
+
+use crate::orbital::MeanElements;
+use crate::search::Satellite;
+use crate::track::{BDeck, CsvTrack};
+
+/// Time between two consecutive epochs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EpochGap {
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Coverage and orbit of the element sets of one satellite
+#[derive(Debug, Clone, Serialize)]
+pub struct TleSummary {
+    pub element_sets: usize,
+    /// Element sets SGP4 cannot initialize
+    pub rejected: usize,
+    pub norad_ids: Vec<u64>,
+    pub first_epoch: Option<f64>,
+    pub last_epoch: Option<f64>,
+    /// Median time between consecutive epochs in seconds
+    pub median_spacing_sec: Option<f64>,
+    /// Longest time without a new element set
+    pub largest_gap: Option<EpochGap>,
+    pub geostationary: bool,
+    /// Elements of the latest usable set
+    pub latest: Option<MeanElements>,
+}
+
+impl TleSummary {
+    pub fn new(satellite: &Satellite) -> Self {
+        let epochs: Vec<f64> = satellite.tle_manager.tles.iter().map(|tle| tle.epoch()).collect();
+        let gaps: Vec<EpochGap> = epochs.windows(2).map(|pair| EpochGap { start: pair[0], end: pair[1] }).collect();
+        let mut spacings: Vec<f64> = gaps.iter().map(|gap| gap.end - gap.start).collect();
+        spacings.sort_by(f64::total_cmp);
+        let mut norad_ids: Vec<u64> = satellite.orbitals.iter().flatten().map(|orbital| orbital.norad_id()).collect();
+        norad_ids.sort_unstable();
+        norad_ids.dedup();
+        TleSummary {
+            element_sets: epochs.len(),
+            rejected: satellite.orbitals.iter().filter(|orbital| orbital.is_none()).count(),
+            norad_ids,
+            first_epoch: epochs.first().copied(),
+            last_epoch: epochs.last().copied(),
+            median_spacing_sec: spacings.get(spacings.len() / 2).copied(),
+            largest_gap: gaps.into_iter().max_by(|a, b| (a.end - a.start).total_cmp(&(b.end - b.start))),
+            geostationary: satellite.is_geostationary(),
+            latest: satellite.orbitals.iter().rev().flatten().next().map(|orbital| orbital.mean_elements()),
+        }
+    }
+}
+
+/// One fix of a track
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TrackFix {
+    pub time: f64,
+    pub latitude: f64,
+    /// Degrees east, [0, 360)
+    pub longitude: f64,
+    pub intensity: Option<f64>,
+}
+
+/// Extent and intensity of a track
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackSummary {
+    pub fixes: usize,
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    /// Southernmost and northernmost latitude
+    pub latitude_range: Option<(f64, f64)>,
+    /// Westernmost and easternmost longitude in degrees east, [0, 360)
+    pub longitude_range: Option<(f64, f64)>,
+    /// Geodesic length through the fixes in km
+    pub length_km: f64,
+    /// Fix of the highest intensity, the first if repeated
+    pub peak: Option<TrackFix>,
+    /// Intensity threshold of `intense`, kt
+    pub min_intensity: f64,
+    /// Times of the first and last fix at or above `min_intensity`
+    pub intense: Option<(f64, f64)>,
+}
+
+impl TrackSummary {
+    /// Summary of fixes given as parallel arrays, intensity in kt if known
+    pub fn new(
+        time: &[f64],
+        latitude: &[f64],
+        longitude: &[f64],
+        intensity: Option<&[f64]>,
+        min_intensity: f64,
+    ) -> Self {
+        let fix = |k: usize| TrackFix {
+            time: time[k],
+            latitude: latitude[k],
+            longitude: longitude[k],
+            intensity: intensity.map(|intensity| intensity[k]),
+        };
+        let range = |values: &[f64]| {
+            values.iter().fold(None, |range: Option<(f64, f64)>, &value| match range {
+                Some((low, high)) => Some((low.min(value), high.max(value))),
+                None => Some((value, value)),
+            })
+        };
+        let geod = Geodesic::wgs84();
+        let length_m: f64 = (1..time.len())
+            .map(|k| -> f64 { geod.inverse(latitude[k - 1], longitude[k - 1], latitude[k], longitude[k]) })
+            .sum();
+        let peak = intensity.and_then(|intensity| {
+            (0..intensity.len()).reduce(|best, k| if intensity[k] > intensity[best] { k } else { best })
+        });
+        let intense = intensity.and_then(|intensity| {
+            let mut above = (0..intensity.len()).filter(|&k| intensity[k] >= min_intensity);
+            let first = above.next()?;
+            Some((time[first], time[above.next_back().unwrap_or(first)]))
+        });
+        TrackSummary {
+            fixes: time.len(),
+            start: time.first().copied(),
+            end: time.last().copied(),
+            latitude_range: range(latitude),
+            longitude_range: range(longitude),
+            length_km: length_m / 1000.0,
+            peak: peak.map(fix),
+            min_intensity,
+            intense,
+        }
+    }
+
+    pub fn from_bdeck(bdeck: &BDeck, min_intensity: f64) -> Self {
+        Self::new(&bdeck.time, &bdeck.latitude, &bdeck.longitude, Some(&bdeck.intensity), min_intensity)
+    }
+
+    pub fn from_csv(track: &CsvTrack, min_intensity: f64) -> Self {
+        Self::new(&track.time, &track.latitude, &track.longitude, track.intensity.as_deref(), min_intensity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_track_summary() {
+        let time = [0.0, 21600.0, 43200.0, 64800.0];
+        let latitude = [10.0, 11.0, 12.0, 13.0];
+        let longitude = [140.0, 139.0, 138.0, 137.0];
+        let intensity = [60.0, 100.0, 120.0, 90.0];
+        let summary = TrackSummary::new(&time, &latitude, &longitude, Some(&intensity), 100.0);
+        assert_eq!(summary.fixes, 4);
+        assert_eq!(summary.latitude_range, Some((10.0, 13.0)));
+        assert_eq!(summary.longitude_range, Some((137.0, 140.0)));
+        // Three steps of about 155 km
+        assert!((summary.length_km - 465.0).abs() < 5.0, "{}", summary.length_km);
+        assert_eq!(summary.peak.map(|fix| fix.time), Some(43200.0));
+        assert_eq!(summary.intense, Some((21600.0, 43200.0)));
+
+        let summary = TrackSummary::new(&time, &latitude, &longitude, None, 100.0);
+        assert_eq!((summary.peak, summary.intense), (None, None));
+    }
+}
@@ -0,0 +1,34 @@
+//! Satellite overpasses of moving targets such as tropical cyclones.
+//!
+//! Storm tracks are read with [`track::BDeck`], two-line element sets with
+//! [`tle::TLEManager`], and [`search::find_tc_passes`] finds the passes of one
+//! satellite over the storm. [`orbital`] holds the orbit model and the event
+//! types returned by the search.
+//!
+//! ```no_run
+//! use satpass::search::{Satellite, SearchParams, find_tc_passes, search_range};
+//! use satpass::tle::TLEManager;
+//! use satpass::track::BDeck;
+//!
+//! let (satellite, _rejected) = Satellite::new(TLEManager::from_file("aqua.txt").unwrap());
+//! let bdeck = BDeck::from_file("bwp312013.dat").unwrap();
+//! let params = SearchParams { min_intensity: 64.0, ..SearchParams::default() };
+//! // Orbits are propagated once over the searched times, for any number of tracks
+//! let (start_utc, stop_utc) = search_range(&bdeck, &params).unwrap();
+//! let ephemeris = satellite.ephemeris(start_utc, stop_utc);
+//! for event in find_tc_passes(&satellite, ephemeris.as_ref(), &bdeck, &params).events {
+//!     println!("{} {:.0} km", event.cpa_time, event.cpa_distance);
+//! }
+//! ```
+
+pub mod collocate;
+pub mod config;
+pub mod granule;
+pub mod info;
+pub mod manifest;
+pub mod map;
+pub mod orbital;
+pub mod output;
+pub mod search;
+pub mod tle;
+pub mod track;
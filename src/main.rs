@@ -1,131 +1,887 @@
-use chrono::{DateTime, Timelike, Utc};
-use clap::Parser;
-use rayon::prelude::*;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::Path;
 
-mod bdeck;
-mod tle;
-mod orbital;
-use orbital::*;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use rayon::prelude::*;
 
-fn dt_from_unix_seconds(t_utc: f64) -> DateTime<Utc> {
-    let micros = (t_utc * 1_000_000.0).round() as i64;
-    DateTime::<Utc>::from_timestamp_micros(micros).expect("timestamp out of range")
-}
+use satpass::collocate;
+use satpass::config::ConfigFile;
+use satpass::granule::{Archive, GranuleSpan, Product};
+use satpass::info::{TleSummary, TrackSummary};
+use satpass::manifest::{Downloader, Manifest};
+use satpass::map::MapLayers;
+use satpass::orbital::{Ephemeris, GeoScanSchedule, GeoSector, OrbitNode, TCSatPassEvent};
+use satpass::output::{
+    dt_from_unix_seconds, intensity_fmt, EventSource, EventWriter, OutputFormat, RunMetadata,
+    WriterOptions,
+};
+use satpass::search::{self, Satellite, SearchParams};
+use satpass::tle::TLEManager;
+use satpass::track::{self, BDeck, CsvTrack, InputError, Site, Track};
 
 #[derive(Parser, Debug)]
 #[command(name = "satpass")]
 #[command(about = "Compute satellite passes from b-deck tracks", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Config {
-    #[arg(value_name = "TLE_FILE")]
-    tle_path: String,
-    #[arg(value_name = "BDECK_FILE")]
-    bdeck_path: String,
-    #[arg(short = 's', long = "step-hours", default_value_t = 6.0, value_name = "hours")]
-    step_hours: f64,
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Read option defaults from this TOML file instead of ~/.config/satpass/config.toml
+    #[arg(long = "config", global = true, value_name = "file")]
+    config_path: Option<String>,
+    /// Without a subcommand, the arguments of passes
+    #[command(flatten)]
+    passes: PassesArgs,
+}
+
+impl Config {
+    fn into_command(self) -> Command {
+        self.command.unwrap_or(Command::Passes(self.passes))
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Find passes of one satellite over a storm or other track, the default command
+    Passes(PassesArgs),
+    /// Find passes over fixed sites in a time range
+    Sites(SitesArgs),
+    /// Find near-simultaneous passes of two or more satellites over the storm
+    Collocate(CollocateArgs),
+    /// Summarize the element sets of TLE files
+    TleInfo(TleInfoArgs),
+    /// Summarize B-Deck or CSV tracks
+    TrackInfo(TrackInfoArgs),
+}
+
+#[derive(Args, Debug)]
+struct PassesArgs {
+    // Optional so that the top-level copy is not required with a subcommand
+    #[arg(value_name = "TLE_FILE", required = true)]
+    tle_path: Option<String>,
+    /// B-Deck or CSV tracks, directories of b*.dat files or quoted glob patterns
+    #[arg(value_name = "BDECK_FILE", required = true, num_args = 1..)]
+    bdeck_paths: Vec<String>,
+    #[command(flatten)]
+    search: SearchArgs,
+    #[arg(short = 'd', long = "distance", default_value_t = 1165., value_name = "km")]
+    distance_thres: f64,
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+struct TleInfoArgs {
+    #[arg(value_name = "TLE_FILE", required = true)]
+    tle_paths: Vec<String>,
+    /// Print the summaries as JSON
+    #[arg(long = "json", default_value_t = false, value_name = "bool")]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct TrackInfoArgs {
+    /// B-Deck or CSV tracks, directories of b*.dat files or quoted glob patterns
+    #[arg(value_name = "BDECK_FILE", required = true)]
+    track_paths: Vec<String>,
+    /// Report when the track is at or above this intensity
     #[arg(short = 'i', long = "intensity", default_value_t = 100.0, value_name = "kt")]
     intensity_thres: f64,
+    /// Print the summaries as JSON
+    #[arg(long = "json", default_value_t = false, value_name = "bool")]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct CollocateArgs {
+    #[arg(value_name = "BDECK_FILE")]
+    bdeck_path: String,
+    #[arg(short = 't', long = "tle", value_name = "TLE_FILE", required = true, num_args = 1)]
+    tle_paths: Vec<String>,
+    /// Swath half-width per satellite, or one value for all
+    #[arg(short = 'd', long = "swath", default_value = "1165", value_name = "km", num_args = 1)]
+    swaths: Vec<f64>,
+    #[arg(short = 'w', long = "window", default_value_t = 30.0, value_name = "min")]
+    window_min: f64,
+    #[command(flatten)]
+    search: SearchArgs,
+}
+
+#[derive(Args, Debug)]
+struct SitesArgs {
+    /// CSV list of sites with name, lat, lon and optional alt (m) columns
+    #[arg(value_name = "SITES_FILE")]
+    sites_path: String,
+    #[arg(short = 't', long = "tle", value_name = "TLE_FILE")]
+    tle_path: String,
+    /// Start of the search, RFC 3339 or YYYY-MM-DD[ HH:MM[:SS]] UTC
+    #[arg(long = "start", value_name = "time", value_parser = parse_time_arg)]
+    start_utc: f64,
+    /// End of the search, same formats as --start
+    #[arg(long = "end", value_name = "time", value_parser = parse_time_arg)]
+    end_utc: f64,
     #[arg(short = 'd', long = "distance", default_value_t = 1165., value_name = "km")]
     distance_thres: f64,
+    #[command(flatten)]
+    search: SearchArgs,
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+struct OutputArgs {
+    /// Name Aqua MODIS granules, same as --product MYD021KM
     #[arg(long = "aqua", default_value_t = false, value_name = "bool")]
     is_aqua: bool,
+    /// Name Terra MODIS granules, same as --product MOD021KM
     #[arg(long = "terra", default_value_t = false, value_name = "bool")]
     is_terra: bool,
+    /// Name the granules of a built-in product, repeat for more products
+    #[arg(long = "product", value_name = "name")]
+    products: Vec<String>,
+    /// Name the granules of a product with a template, e.g. "MYD06_L2=MYD06_L2.A{start:%Y%j.%H%M}"; repeat for more
+    #[arg(long = "granule-template", value_name = "name=template")]
+    granule_templates: Vec<String>,
+    /// Granule length of --granule-template products
+    #[arg(long = "granule-minutes", default_value_t = 5.0, value_name = "min")]
+    granule_minutes: f64,
+    /// Print the storm-relative sub-satellite track under each pass
+    #[arg(long = "track", default_value_t = false, value_name = "bool")]
+    print_track: bool,
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text, value_name = "format")]
+    format: OutputFormat,
+    /// Write events to this file instead of stdout
+    #[arg(short = 'o', long = "output", value_name = "file")]
+    output_path: Option<String>,
+    /// Write ground tracks, swaths and target tracks to this GeoJSON file, or KML if it ends in .kml
+    #[arg(long = "map", value_name = "file")]
+    map_path: Option<String>,
+    /// Write the archive directory of each granule to this CSV file
+    #[arg(long = "manifest", value_name = "file")]
+    manifest_path: Option<String>,
+    /// Write a script downloading the granules to this file
+    #[arg(long = "download-script", value_name = "file")]
+    script_path: Option<String>,
+    /// Program the download script runs
+    #[arg(long = "downloader", value_enum, default_value_t = Downloader::Wget, value_name = "program")]
+    downloader: Downloader,
+    /// Archive directory URL template for all products, e.g.
+    /// "https://host/{collection}/{product}/{start:%Y}/{start:%j}/"
+    #[arg(long = "archive-url", value_name = "template")]
+    archive_url: Option<String>,
+    /// Archive collection or version, e.g. 61 for MODIS Collection 6.1
+    #[arg(long = "collection", value_name = "id")]
+    collection: Option<String>,
+}
+
+// Target tracks on maps are sampled hourly
+const MAP_TRACK_STEP_SEC: f64 = 3600.0;
+
+impl OutputArgs {
+    fn writer(&self, label_lines: bool, metadata: RunMetadata) -> io::Result<EventWriter> {
+        let out: Box<dyn Write> = match &self.output_path {
+            Some(path) => {
+                let file = std::fs::File::create(path)
+                    .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))?;
+                Box::new(io::BufWriter::new(file))
+            }
+            None => Box::new(io::stdout().lock()),
+        };
+        let options = WriterOptions {
+            format: self.format,
+            print_track: self.print_track,
+            label_lines,
+            metadata: Some(metadata),
+        };
+        Ok(EventWriter::new(out, options))
+    }
+
+    fn wants_manifest(&self) -> bool {
+        self.manifest_path.is_some() || self.script_path.is_some()
+    }
+
+    fn write_manifest(&self, manifest: &Manifest) -> io::Result<()> {
+        if !self.wants_manifest() {
+            return Ok(());
+        }
+        for name in manifest.unarchived() {
+            eprintln!("Warning: no archive known for {}, use --archive-url", name);
+        }
+        let create = |path: &String| {
+            std::fs::File::create(path)
+                .map(io::BufWriter::new)
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))
+        };
+        if let Some(path) = &self.manifest_path {
+            manifest.write_csv(create(path)?)?;
+        }
+        if let Some(path) = &self.script_path {
+            manifest.write_script(create(path)?, self.downloader)?;
+        }
+        Ok(())
+    }
+
+    fn write_map(&self, layers: &MapLayers) -> io::Result<()> {
+        let Some(path) = &self.map_path else {
+            return Ok(());
+        };
+        let file = std::fs::File::create(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))?;
+        let out = io::BufWriter::new(file);
+        let is_kml = std::path::Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("kml"));
+        if is_kml {
+            layers.write_kml(out)
+        } else {
+            layers.write_geojson(out)
+        }
+    }
+
+    /// Products to name granules of, in the order given
+    fn products(&self) -> Result<Vec<Product>, String> {
+        if self.granule_minutes <= 0.0 {
+            return Err("--granule-minutes must be > 0".to_string());
+        }
+        let mut names = Vec::new();
+        if self.is_aqua {
+            names.push("MYD021KM");
+        }
+        if self.is_terra {
+            names.push("MOD021KM");
+        }
+        names.extend(self.products.iter().map(String::as_str));
+        let mut products = names
+            .iter()
+            .map(|name| {
+                Product::preset(name).ok_or_else(|| {
+                    format!("unknown product '{}', expected one of {}", name, Product::preset_names().join(", "))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for value in self.granule_templates.iter() {
+            let invalid = |msg: String| format!("--granule-template '{}': {}", value, msg);
+            let (name, template) = value
+                .split_once('=')
+                .filter(|(name, _)| !name.trim().is_empty())
+                .ok_or_else(|| invalid("expected NAME=TEMPLATE".to_string()))?;
+            let span = GranuleSpan::Duration { duration_sec: self.granule_minutes * 60.0, offset_sec: 0.0 };
+            let product = Product::new(name.trim(), template, span).map_err(|err| invalid(err.to_string()))?;
+            products.push(product);
+        }
+        if self.wants_manifest() && products.is_empty() {
+            return Err("--manifest and --download-script need a product or granule template".to_string());
+        }
+        if let Some(url) = &self.archive_url {
+            let archive = Archive::new(url, self.collection.as_deref().unwrap_or(""))
+                .map_err(|err| format!("--archive-url '{}': {}", url, err))?;
+            for product in products.iter_mut() {
+                product.archive = Some(archive.clone());
+            }
+        } else if let Some(collection) = &self.collection {
+            for product in products.iter_mut() {
+                if let Some(archive) = &product.archive {
+                    product.archive = Some(
+                        archive
+                            .with_collection(collection)
+                            .map_err(|err| format!("--collection '{}': {}", collection, err))?,
+                    );
+                }
+            }
+        }
+        Ok(products)
+    }
+}
+
+fn granule_names(products: &[Product], event: &TCSatPassEvent) -> Vec<String> {
+    products.iter().flat_map(|product| product.granule_names(event)).collect()
+}
+
+fn parse_time_arg(value: &str) -> Result<f64, String> {
+    track::parse_time(value).ok_or_else(|| format!("unrecognized time '{}'", value))
+}
+
+// NORAD ID and revolution offset
+fn parse_revolution_offset(value: &str) -> Result<(u64, u64), String> {
+    value
+        .split_once('=')
+        .and_then(|(norad_id, offset)| Some((norad_id.trim().parse().ok()?, offset.trim().parse().ok()?)))
+        .ok_or_else(|| format!("expected NORAD_ID=REVOLUTIONS, got '{}'", value))
+}
+
+// South, north, west and east bounds of a sector
+fn parse_sector_box(value: &str) -> Result<[f64; 4], String> {
+    let invalid = || format!("expected SOUTH,NORTH,WEST,EAST in degrees, got '{}'", value);
+    let bounds: Vec<f64> =
+        value.split(',').map(|bound| bound.trim().parse()).collect::<Result<_, _>>().map_err(|_| invalid())?;
+    let [south, north, west, east] = bounds[..] else {
+        return Err(invalid());
+    };
+    if !(-90.0..=90.0).contains(&south) || !(-90.0..=90.0).contains(&north) || south >= north {
+        return Err(format!("sector latitudes must be within [-90, 90] with south < north, got '{}'", value));
+    }
+    Ok([south, north, west, east])
+}
+
+#[derive(Args, Debug)]
+struct SearchArgs {
+    #[arg(short = 's', long = "step-hours", default_value_t = 6.0, value_name = "hours")]
+    step_hours: f64,
+    #[arg(short = 'i', long = "intensity", default_value_t = 100.0, value_name = "kt")]
+    intensity_thres: f64,
+    #[arg(long = "node", value_enum, value_name = "node")]
+    node: Option<OrbitNode>,
+    #[arg(long = "geo-interval", default_value_t = 10.0, value_name = "min")]
+    geo_interval_min: f64,
+    #[arg(long = "geo-scan-duration", default_value_t = 10.0, value_name = "min")]
+    geo_duration_min: f64,
+    #[arg(long = "geo-south-north", default_value_t = false, value_name = "bool")]
+    geo_south_north: bool,
+    #[arg(long = "geo-max-zenith", default_value_t = 80.0, value_name = "deg")]
+    geo_max_zenith: f64,
+    /// Target-area sector of the geostationary imager, a latitude and longitude box in degrees
+    #[arg(long = "geo-sector", value_name = "S,N,W,E", value_parser = parse_sector_box, allow_hyphen_values = true)]
+    geo_sector: Option<[f64; 4]>,
+    #[arg(long = "geo-sector-interval", default_value_t = 2.5, value_name = "min")]
+    geo_sector_interval_min: f64,
+    #[arg(long = "geo-sector-duration", default_value_t = 2.5, value_name = "min")]
+    geo_sector_duration_min: f64,
+    /// Name every granule in which the swath covers this radius around the storm center
+    #[arg(long = "footprint-radius", default_value_t = 0.0, value_name = "km")]
+    footprint_radius: f64,
+    /// Reach of the swath to either side of the ground track, for --footprint-radius and --map
+    #[arg(long = "swath-half-width", default_value_t = 1165.0, value_name = "km")]
+    swath_half_width: f64,
+    /// Revolutions of a satellite beyond the 5-digit TLE revolution field, e.g. 27424=100000
+    #[arg(long = "revolution-offset", value_name = "NORAD=REVS", value_parser = parse_revolution_offset)]
+    revolution_offsets: Vec<(u64, u64)>,
+}
+
+impl SearchArgs {
+    fn validate(&self) -> Result<(), String> {
+        if self.step_hours <= 0.0 {
+            return Err("--step-hours must be > 0".to_string());
+        }
+        if self.intensity_thres < 0.0 {
+            return Err("--intensity must be >= 0".to_string());
+        }
+        if self.footprint_radius < 0.0 || self.swath_half_width < 0.0 {
+            return Err("--footprint-radius and --swath-half-width must be >= 0".to_string());
+        }
+        if self.geo_interval_min <= 0.0 || self.geo_duration_min < 0.0 {
+            return Err("--geo-interval must be > 0 and --geo-scan-duration >= 0".to_string());
+        }
+        if self.geo_sector_interval_min <= 0.0 || self.geo_sector_duration_min < 0.0 {
+            return Err("--geo-sector-interval must be > 0 and --geo-sector-duration >= 0".to_string());
+        }
+        Ok(())
+    }
+
+    fn params(&self, max_distance: f64) -> SearchParams {
+        SearchParams {
+            step_sec: self.step_hours * 3600.0,
+            min_intensity: self.intensity_thres,
+            max_distance,
+            node: self.node,
+            geo_schedule: GeoScanSchedule {
+                interval_sec: self.geo_interval_min * 60.0,
+                duration_sec: self.geo_duration_min * 60.0,
+                south_to_north: self.geo_south_north,
+                sector: self.geo_sector.map(|[south, north, west, east]| GeoSector {
+                    interval_sec: self.geo_sector_interval_min * 60.0,
+                    duration_sec: self.geo_sector_duration_min * 60.0,
+                    south,
+                    north,
+                    west,
+                    east,
+                }),
+            },
+            geo_max_zenith: self.geo_max_zenith,
+            footprint_radius: self.footprint_radius,
+            swath_half_width: self.swath_half_width,
+        }
+    }
+}
+
+// Print `msg` as "Error: <msg>" and end the run with a failing exit status
+fn exit_with_error(msg: impl std::fmt::Display) -> ! {
+    eprintln!("Error: {}", msg);
+    std::process::exit(1)
+}
+
+// The value of `result`, or its error through `exit_with_error`. Invalid
+// options, input files that cannot be read and failed writes end the run.
+fn or_exit<T, E: std::fmt::Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|err| exit_with_error(err))
+}
+
+fn load_satellite(tle_path: &str, search: &SearchArgs) -> Satellite {
+    let (mut satellite, errors) = Satellite::new(or_exit(TLEManager::from_file(tle_path)));
+    for err in errors {
+        eprintln!("Warning: ignoring TLE in {}: {}", tle_path, err);
+    }
+    for (norad_id, offset) in search.revolution_offsets.iter() {
+        satellite.add_revolution_offset(*norad_id, *offset);
+    }
+    satellite
 }
 
-fn modis_name_fmt(scan_time: DateTime<Utc>, is_aqua: bool) -> String {
-    // Round to most recent 5 minute increment
-    let minute = scan_time.minute();
-    let rounded_minute = (minute / 5) * 5;
-    let scan_time = scan_time
-        .with_minute(rounded_minute)
-        .unwrap();
-    let date_time = scan_time.format(".A%Y%j.%H%M").to_string();
-    if is_aqua {
-        format!("MYD021KM{}", date_time)
+fn file_stem(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_stem()
+        .map_or_else(|| path.to_string(), |stem| stem.to_string_lossy().into_owned())
+}
+
+// CSV tracks are recognized by extension, anything else is read as B-Deck
+fn is_csv_path(track_path: &str) -> bool {
+    Path::new(track_path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+}
+
+// Storm ID of a B-Deck, or the file name if it has none
+fn storm_id(bdeck: &BDeck, track_path: &str) -> String {
+    if bdeck.storm_id.is_empty() {
+        file_stem(track_path)
     } else {
-        format!("MOD021KM{}", date_time)
+        bdeck.storm_id.clone()
     }
 }
 
-fn main() {
-    let config = Config::parse();
-    if config.step_hours <= 0.0 {
-        eprintln!("Error: --step-hours must be > 0");
-        return;
+// B-Deck files in a directory given as a track
+const BDECK_PATTERN: &str = "b*.dat";
+
+/// Track files named by the arguments: files as given, the B-Deck files of
+/// directories and the matches of glob patterns, each in name order
+fn expand_track_paths(args: &[String]) -> Result<Vec<String>, String> {
+    let mut paths = Vec::new();
+    for arg in args {
+        let pattern = if Path::new(arg).is_dir() {
+            format!("{}/{}", glob::Pattern::escape(arg.trim_end_matches('/')), BDECK_PATTERN)
+        } else if arg.contains(['*', '?', '[']) {
+            arg.clone()
+        } else {
+            paths.push(arg.clone());
+            continue;
+        };
+        let matches: Vec<String> = glob::glob(&pattern)
+            .map_err(|err| format!("invalid pattern '{}': {}", pattern, err))?
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file())
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        if matches.is_empty() {
+            return Err(format!("no track files match '{}'", pattern));
+        }
+        paths.extend(matches);
+    }
+    Ok(paths)
+}
+
+// Returns the track with its storm ID, or the file name for CSV tracks
+fn load_track(track_path: &str) -> Result<(String, Box<dyn Track + Send + Sync>), InputError> {
+    if is_csv_path(track_path) {
+        Ok((file_stem(track_path), Box::new(CsvTrack::from_file(track_path)?)))
+    } else {
+        let bdeck = BDeck::from_file(track_path)?;
+        Ok((storm_id(&bdeck, track_path), Box::new(bdeck)))
+    }
+}
+
+/// Reads every track file in parallel, in input order. A single file that
+/// cannot be read ends the program; of several, the unreadable ones are
+/// skipped with a warning.
+fn load_tracks<T: Send>(
+    track_paths: &[String],
+    load: impl Fn(&str) -> Result<T, InputError> + Sync,
+) -> Vec<(&String, T)> {
+    if let [track_path] = track_paths {
+        return vec![(track_path, or_exit(load(track_path)))];
+    }
+    let results: Vec<_> = track_paths.par_iter().map(|track_path| (track_path, load(track_path))).collect();
+    results
+        .into_iter()
+        .filter_map(|(track_path, result)| match result {
+            Ok(track) => Some((track_path, track)),
+            Err(err) => {
+                eprintln!("Warning: skipping {}", err);
+                None
+            }
+        })
+        .collect()
+}
+
+fn find_tc_passes(
+    satellite: &Satellite,
+    ephemeris: Option<&Ephemeris>,
+    target: &str,
+    track: &(dyn Track + Sync),
+    params: &SearchParams,
+) -> Vec<TCSatPassEvent> {
+    let result = search::find_tc_passes(satellite, ephemeris, track, params);
+    for (time, err) in result.skipped.iter() {
+        eprintln!(
+            "Warning: skipping {} track point {}: {}",
+            target,
+            dt_from_unix_seconds(*time).format("%Y-%m-%d %H:%M"),
+            err
+        );
+    }
+    result.events
+}
+
+fn run_collocate(args: &CollocateArgs) {
+    or_exit(args.search.validate());
+    if args.tle_paths.len() < 2 {
+        exit_with_error("collocate needs at least two --tle files");
+    }
+    if args.swaths.len() != 1 && args.swaths.len() != args.tle_paths.len() {
+        exit_with_error("give one --swath for all satellites or one per --tle");
+    }
+    if args.swaths.iter().any(|swath| *swath < 0.0) || args.window_min < 0.0 {
+        exit_with_error("--swath and --window must be >= 0");
+    }
+    let (storm_id, track) = or_exit(load_track(&args.bdeck_path));
+    let names: Vec<String> = args.tle_paths.iter().map(|path| file_stem(path)).collect();
+    let events: Vec<Vec<TCSatPassEvent>> = args
+        .tle_paths
+        .iter()
+        .enumerate()
+        .map(|(k, tle_path)| {
+            let swath = if args.swaths.len() == 1 { args.swaths[0] } else { args.swaths[k] };
+            let params = args.search.params(swath);
+            let satellite = load_satellite(tle_path, &args.search);
+            let ephemeris = search::search_range(track.as_ref(), &params)
+                .and_then(|(start_utc, stop_utc)| satellite.ephemeris(start_utc, stop_utc));
+            find_tc_passes(&satellite, ephemeris.as_ref(), &storm_id, track.as_ref(), &params)
+        })
+        .collect();
+
+    for pair in collocate::match_passes(&events, args.window_min * 60.0) {
+        println!("{} {} - {} {}  Offset: {:+6.1} min  dZenith: {:+5.1}°  dDistance: {:+5.0} km  Intensity: {} kt",
+            dt_from_unix_seconds(pair.first.cpa_time).format("%Y-%m-%d %H:%M:%S"),
+            names[pair.first_satellite],
+            dt_from_unix_seconds(pair.second.cpa_time).format("%Y-%m-%d %H:%M:%S"),
+            names[pair.second_satellite],
+            pair.time_offset / 60.0,
+            pair.zenith_difference,
+            pair.distance_difference,
+            intensity_fmt(pair.first.intensity));
+    }
+}
+
+fn run_sites(args: &SitesArgs) {
+    or_exit(args.search.validate());
+    if args.distance_thres < 0.0 {
+        exit_with_error("--distance must be >= 0");
+    }
+    if args.end_utc <= args.start_utc {
+        exit_with_error("--end must be after --start");
+    }
+    let products = or_exit(args.output.products());
+    let sites = or_exit(Site::read_list(&args.sites_path));
+    let satellite = load_satellite(&args.tle_path, &args.search);
+    let satellite_name = file_stem(&args.tle_path);
+    let params = args.search.params(args.distance_thres);
+    // All sites are searched over the same times
+    let ephemeris = satellite.ephemeris(args.start_utc, args.end_utc);
+    let metadata = RunMetadata::new(
+        "sites",
+        vec![args.sites_path.clone(), args.tle_path.clone()],
+        params.clone(),
+    )
+    .with_range(args.start_utc, args.end_utc);
+    let write = || {
+        let mut writer = args.output.writer(true, metadata)?;
+        let mut layers = MapLayers::new();
+        let mut manifest = Manifest::new();
+        for site in sites.iter() {
+            let track = site.track(args.start_utc, args.end_utc);
+            let events = find_tc_passes(&satellite, ephemeris.as_ref(), &site.name, &track, &params);
+            let source = EventSource { target: &site.name, satellite: &satellite_name };
+            writer.write_events(&events, &source, |event| granule_names(&products, event))?;
+            layers.add_track(&site.name, &track, MAP_TRACK_STEP_SEC);
+            layers.add_passes(&events, &source, params.swath_half_width);
+            manifest.add_events(&products, &events);
+        }
+        writer.finish()?;
+        args.output.write_map(&layers)?;
+        args.output.write_manifest(&manifest)
+    };
+    or_exit(write());
+}
+
+// The config file is needed before parsing, as its values become the defaults
+fn config_arg(args: &[OsString]) -> Option<&str> {
+    args.iter().enumerate().find_map(|(k, arg)| match arg.to_str()? {
+        "--config" => args.get(k + 1)?.to_str(),
+        arg => arg.strip_prefix("--config="),
+    })
+}
+
+/// Command line over the config file values, with TLE files resolved
+fn parse_command() -> Result<Command, String> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let file = match config_arg(&args) {
+        Some(path) => Some(ConfigFile::load(Path::new(path))),
+        None => ConfigFile::load_default().transpose(),
+    };
+    let Some(file) = file.transpose().map_err(|err| err.to_string())? else {
+        return Ok(Config::parse_from(args).into_command());
+    };
+    let parse = |sensor: Option<&str>| -> Result<Command, String> {
+        let command = file.apply(Config::command(), sensor).map_err(|err| err.to_string())?;
+        let config = Config::from_arg_matches(&command.get_matches_from(&args)).unwrap_or_else(|err| err.exit());
+        Ok(config.into_command())
+    };
+    let mut command = parse(None)?;
+    // A TLE file named after a sensor brings the sensor options
+    let tle_name = match &command {
+        Command::Passes(args) => args.tle_path.clone(),
+        Command::Sites(args) => Some(args.tle_path.clone()),
+        Command::Collocate(_) | Command::TleInfo(_) | Command::TrackInfo(_) => None,
+    };
+    if let Some(name) = tle_name.filter(|name| file.sensor(name).is_some()) {
+        command = parse(Some(&name))?;
+    }
+    match &mut command {
+        Command::Passes(args) => args.tle_path = args.tle_path.as_deref().map(|path| file.resolve_tle(path)),
+        Command::Sites(args) => args.tle_path = file.resolve_tle(&args.tle_path),
+        Command::Collocate(CollocateArgs { tle_paths, .. }) | Command::TleInfo(TleInfoArgs { tle_paths, .. }) => {
+            for path in tle_paths.iter_mut() {
+                *path = file.resolve_tle(path);
+            }
+        }
+        Command::TrackInfo(_) => {}
     }
-    if config.intensity_thres < 0.0 {
-        eprintln!("Error: --intensity must be >= 0");
+    Ok(command)
+}
+
+fn run_tle_info(args: &TleInfoArgs) {
+    let summaries: Vec<(&String, TleSummary)> = args
+        .tle_paths
+        .iter()
+        .map(|path| {
+            let (satellite, _rejected) = Satellite::new(or_exit(TLEManager::from_file(path)));
+            (path, TleSummary::new(&satellite))
+        })
+        .collect();
+    if args.json {
+        let documents: Vec<_> = summaries.iter().map(|(path, summary)| json_with_file(path, summary)).collect();
+        println!("{}", serde_json::to_string_pretty(&documents).unwrap());
         return;
     }
-    if config.distance_thres < 0.0 {
-        eprintln!("Error: --distance must be >= 0");
+    let time = |t_utc: f64| dt_from_unix_seconds(t_utc).format("%Y-%m-%d %H:%M");
+    for (path, summary) in summaries {
+        let ids: Vec<String> = summary.norad_ids.iter().map(u64::to_string).collect();
+        println!(
+            "{}: NORAD {}, {} element sets, {} rejected{}",
+            path,
+            if ids.is_empty() { "-".to_string() } else { ids.join(" ") },
+            summary.element_sets,
+            summary.rejected,
+            if summary.geostationary { ", geostationary" } else { "" }
+        );
+        if let (Some(first), Some(last)) = (summary.first_epoch, summary.last_epoch) {
+            print!("  Epochs: {} to {}", time(first), time(last));
+            if let (Some(spacing), Some(gap)) = (summary.median_spacing_sec, summary.largest_gap) {
+                print!(
+                    ", median spacing {:.1} h, largest gap {:.1} h from {}",
+                    spacing / 3600.0,
+                    (gap.end - gap.start) / 3600.0,
+                    time(gap.start)
+                );
+            }
+            println!();
+        }
+        if let Some(elements) = summary.latest {
+            println!(
+                "  Latest: inclination {:.2}°, eccentricity {:.6}, period {:.2} min, perigee {:.0} km, apogee {:.0} km",
+                elements.inclination_deg,
+                elements.eccentricity,
+                elements.period_sec / 60.0,
+                elements.perigee_km,
+                elements.apogee_km
+            );
+        }
+    }
+}
+
+fn run_track_info(args: &TrackInfoArgs) {
+    let track_paths = or_exit(expand_track_paths(&args.track_paths));
+    let summaries: Vec<(&String, String, TrackSummary)> = load_tracks(&track_paths, |path| {
+        Ok(if is_csv_path(path) {
+            let track = CsvTrack::from_file(path)?;
+            (file_stem(path), TrackSummary::from_csv(&track, args.intensity_thres))
+        } else {
+            let bdeck = BDeck::from_file(path)?;
+            (storm_id(&bdeck, path), TrackSummary::from_bdeck(&bdeck, args.intensity_thres))
+        })
+    })
+    .into_iter()
+    .map(|(path, (storm_id, summary))| (path, storm_id, summary))
+    .collect();
+    if args.json {
+        let documents: Vec<_> = summaries
+            .iter()
+            .map(|(path, storm_id, summary)| {
+                let mut document = json_with_file(path, summary);
+                document["storm_id"] = serde_json::json!(storm_id);
+                document
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&documents).unwrap());
         return;
     }
-    let tle_manager = tle::TLEManager::from_file(&config.tle_path).unwrap();
-    let orbitals: Vec<Orbital> = tle_manager
-        .tles
+    let time = |t_utc: f64| dt_from_unix_seconds(t_utc).format("%Y-%m-%d %H:%M");
+    for (path, storm_id, summary) in summaries {
+        print!("{} ({}): {} fixes", storm_id, path, summary.fixes);
+        if let (Some(start), Some(end)) = (summary.start, summary.end) {
+            print!(", {} to {} ({:.0} h)", time(start), time(end), (end - start) / 3600.0);
+        }
+        println!();
+        if let (Some(latitude), Some(longitude)) = (summary.latitude_range, summary.longitude_range) {
+            println!(
+                "  Extent: latitude {:.1} to {:.1}, longitude {:.1} to {:.1} E, path {:.0} km",
+                latitude.0, latitude.1, longitude.0, longitude.1, summary.length_km
+            );
+        }
+        if let Some(peak) = summary.peak {
+            println!(
+                "  Peak: {} kt at {}, {:.1}, {:.1} E",
+                intensity_fmt(peak.intensity),
+                time(peak.time),
+                peak.latitude,
+                peak.longitude
+            );
+            match summary.intense {
+                Some((first, last)) => println!(
+                    "  At or above {:.0} kt: {} to {}",
+                    summary.min_intensity,
+                    time(first),
+                    time(last)
+                ),
+                None => println!("  Never at or above {:.0} kt", summary.min_intensity),
+            }
+        }
+    }
+}
+
+fn json_with_file<T: serde::Serialize>(path: &str, summary: &T) -> serde_json::Value {
+    let mut document = serde_json::to_value(summary).unwrap();
+    document["file"] = serde_json::json!(path);
+    document
+}
+
+fn main() {
+    let command = or_exit(parse_command());
+    match &command {
+        Command::Passes(args) => run_passes(args),
+        Command::Sites(args) => run_sites(args),
+        Command::Collocate(args) => run_collocate(args),
+        Command::TleInfo(args) => run_tle_info(args),
+        Command::TrackInfo(args) => run_track_info(args),
+    }
+}
+
+fn run_passes(args: &PassesArgs) {
+    or_exit(args.search.validate());
+    if args.distance_thres < 0.0 {
+        exit_with_error("--distance must be >= 0");
+    }
+    let products = or_exit(args.output.products());
+    let track_paths = or_exit(expand_track_paths(&args.bdeck_paths));
+    let tle_path = args.tle_path.as_deref().unwrap();
+    let satellite = load_satellite(tle_path, &args.search);
+    let satellite_name = file_stem(tle_path);
+    let params = args.search.params(args.distance_thres);
+    let tracks = load_tracks(&track_paths, load_track);
+    let mut inputs = vec![tle_path.to_string()];
+    inputs.extend(tracks.iter().map(|(track_path, _)| track_path.to_string()));
+    let tracks: Vec<_> = tracks.into_iter().map(|(_, track)| track).collect();
+    // Storms share the element sets and the states propagated over their
+    // times, and are searched in parallel, in input order
+    let ranges: Vec<(f64, f64)> = tracks
         .iter()
-        .map(Orbital::new)
+        .filter_map(|(_, track)| search::search_range(track.as_ref(), &params))
         .collect();
-    let bdeck = bdeck::BDeck::from_file(&config.bdeck_path).unwrap();
-    // Loop over bdeck to find all passes
-    let step_sec = config.step_hours * 3600.0;
-    let intensity_thres = config.intensity_thres;
-    let distance_thres = config.distance_thres;
-    let all_passes: Vec<TCSatPassEvent> = (0..bdeck.time.len())
+    let ephemerides = satellite.ephemerides(&ranges);
+    let storms: Vec<_> = tracks
         .into_par_iter()
-        .map(|i| {
-            let mut acc = Vec::new();
-            let time = bdeck.time[i];
-            let lon = bdeck.longitude[i];
-            let lat = bdeck.latitude[i];
-            let Some(tle_index) = tle_manager.select_tle_index(time) else {
-                return acc;
-            };
-            let orbital = &orbitals[tle_index];
-            let pass_events = orbital.get_passes(time, step_sec, lon, lat);
-            let mut interp_index = i;
-            for pass_event in pass_events {
-                let ptime = pass_event.cpa_time;
-                if let Some((lat_i, lon_i, intens_i)) =
-                    bdeck.interpolate_with_index(ptime, &mut interp_index)
-                {
-                    if intens_i < intensity_thres {
-                        continue;
-                    }
-                    let pass_refined = orbital.get_passes(ptime - 1800.0, 3600.0, lon_i, lat_i);
-                    for refined_event in pass_refined.iter() {
-                        if refined_event.cpa_distance <= distance_thres {
-                            acc.push(TCSatPassEvent {
-                                cpa_time: refined_event.cpa_time,
-                                cpa_distance: refined_event.cpa_distance,
-                                sat_zenith: 90.0 - refined_event.elevation,
-                                intensity: intens_i,
-                            });
-                        }
-                    }
-                }
-            }
-            acc
+        .map(|(storm_id, track)| {
+            let ephemeris = search::search_range(track.as_ref(), &params).and_then(|(start_utc, stop_utc)| {
+                ephemerides
+                    .iter()
+                    .find(|((start, stop), _)| *start <= start_utc && stop_utc <= *stop)
+                    .map(|(_, ephemeris)| ephemeris)
+            });
+            let events = find_tc_passes(&satellite, ephemeris, &storm_id, track.as_ref(), &params);
+            (storm_id, track, events)
         })
-        .collect::<Vec<_>>()
-        .into_iter()
-        .flatten()
         .collect();
+    let metadata = RunMetadata::new("passes", inputs, params.clone());
+    let write = || {
+        let mut writer = args.output.writer(storms.len() > 1, metadata)?;
+        let mut layers = MapLayers::new();
+        let mut manifest = Manifest::new();
+        for (storm_id, track, events) in storms.iter() {
+            let source = EventSource { target: storm_id, satellite: &satellite_name };
+            writer.write_events(events, &source, |event| granule_names(&products, event))?;
+            layers.add_track(storm_id, track.as_ref(), MAP_TRACK_STEP_SEC);
+            layers.add_passes(events, &source, params.swath_half_width);
+            manifest.add_events(&products, events);
+        }
+        writer.finish()?;
+        args.output.write_map(&layers)?;
+        args.output.write_manifest(&manifest)
+    };
+    or_exit(write());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for event in all_passes.iter() {
-        let dt_cpa = dt_from_unix_seconds(event.cpa_time);
-        let mut sat_file_name: String = " ".to_string();
-        if config.is_aqua {
-            sat_file_name = modis_name_fmt(dt_cpa, true);
-        } else if config.is_terra {
-            sat_file_name = modis_name_fmt(dt_cpa, false);
-        }
-        println!("{} - Distance: {:4.0} km  Zenith: {:4.1}° Intensity: {:3.0} kt   {}",
-            dt_cpa.format("%Y-%m-%d %H:%M:%S"),
-            event.cpa_distance,
-            event.sat_zenith,
-            event.intensity,
-            sat_file_name);
+    #[test]
+    fn test_load_track_batch() {
+        let dir = std::env::temp_dir().join("satpass_test_track_batch");
+        std::fs::create_dir_all(&dir).unwrap();
+        let fix = |number: u32, time: &str| {
+            format!("WP, {}, {},   , BEST,   0,  60N, 1600E,  25,   988, TY,\n", number, time)
+        };
+        std::fs::write(dir.join("bwp312013.dat"), fix(31, "2013110300") + &fix(31, "2013110306")).unwrap();
+        std::fs::write(dir.join("bwp322013.dat"), fix(32, "2013111000")).unwrap();
+        std::fs::write(dir.join("bwp332013.dat"), "WP, 33, 2013\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a track\n").unwrap();
+
+        let track_paths = expand_track_paths(&[dir.to_string_lossy().into_owned()]).unwrap();
+        let names: Vec<String> = track_paths.iter().map(|path| file_stem(path)).collect();
+        assert_eq!(names, ["bwp312013", "bwp322013", "bwp332013"]);
+        let tracks = load_tracks(&track_paths, load_track);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let loaded: Vec<(&String, &str)> =
+            tracks.iter().map(|(path, (storm_id, _))| (*path, storm_id.as_str())).collect();
+        assert_eq!(loaded, [(&track_paths[0], "WP312013"), (&track_paths[1], "WP322013")]);
+        assert_eq!(tracks[0].1.1.time_range(), Some((1383436800.0, 1383458400.0)));
+    }
+
+    #[test]
+    fn test_granule_template_products() {
+        #[derive(Parser)]
+        struct Cli {
+            #[command(flatten)]
+            output: OutputArgs,
+        }
+        let products = |template: &str| {
+            Cli::try_parse_from(["satpass", "--granule-template", template]).unwrap().output.products()
+        };
+        let parsed = products("MYD06_L2=MYD06_L2.A{start:%Y%j.%H%M}").unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].name, "MYD06_L2");
+        assert!(products("MYD06_L2.A{start:%Y%j.%H%M}").unwrap_err().contains("expected NAME=TEMPLATE"));
+        assert!(products("=MYD06_L2.A{start:%Y%j.%H%M}").unwrap_err().contains("expected NAME=TEMPLATE"));
+        assert!(products("MYD06_L2=MYD06_L2.A{start").unwrap_err().contains("unbalanced braces"));
     }
 }
@@ -0,0 +1,171 @@
+//! Download lists for the granules observing passes: a CSV manifest of
+//! archive directories and a wget or curl script
+//!
+//! Granule names are the start of the archived file names, so the scripts
+//! list each directory and fetch the files whose names begin with the
+//! granule. Nothing here contacts the archives.
+
+use std::io::{self, Write};
+
+use crate::granule::Product;
+use crate::orbital::TCSatPassEvent;
+use crate::output::csv_field;
+
+/// Program the download script runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Downloader {
+    Wget,
+    Curl,
+}
+
+/// One granule to download
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    pub product: String,
+    /// Granule name, the start of the file name
+    pub granule: String,
+    /// Archive directory URL, ending in `/`
+    pub directory: String,
+}
+
+/// Granules collected for one download, each listed once
+#[derive(Debug, Default)]
+pub struct Manifest {
+    entries: Vec<ManifestEntry>,
+    unarchived: Vec<String>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    /// Products whose granules were left out because their archive is unknown
+    pub fn unarchived(&self) -> &[String] {
+        &self.unarchived
+    }
+
+    /// Add the granules of `products` observing `events`
+    pub fn add_events(&mut self, products: &[Product], events: &[TCSatPassEvent]) {
+        for product in products {
+            let Some(archive) = &product.archive else {
+                if !self.unarchived.contains(&product.name) {
+                    self.unarchived.push(product.name.clone());
+                }
+                continue;
+            };
+            for granule in events.iter().flat_map(|event| product.granules(event)) {
+                let entry = ManifestEntry {
+                    product: product.name.clone(),
+                    granule: product.template.render(&granule),
+                    directory: archive.directory(&product.name, &granule),
+                };
+                if !self.entries.contains(&entry) {
+                    self.entries.push(entry);
+                }
+            }
+        }
+    }
+
+    /// CSV with a header and one row per granule
+    pub fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "product,granule,directory")?;
+        for entry in self.entries.iter() {
+            let fields = [&entry.product, &entry.granule, &entry.directory].map(|field| csv_field(field));
+            writeln!(out, "{}", fields.join(","))?;
+        }
+        out.flush()
+    }
+
+    /// Bash script fetching every granule into the working directory. An
+    /// Earthdata Login token in `EARTHDATA_TOKEN` is sent as a bearer token.
+    pub fn write_script<W: Write>(&self, mut out: W, downloader: Downloader) -> io::Result<()> {
+        writeln!(out, "#!/usr/bin/env bash")?;
+        writeln!(out, "# Granules observing satpass passes, {} files", self.entries.len())?;
+        writeln!(out, "# Set EARTHDATA_TOKEN to an Earthdata Login token for NASA archives.")?;
+        writeln!(out, "set -euo pipefail")?;
+        writeln!(out)?;
+        writeln!(out, "auth=()")?;
+        writeln!(out, "if [ -n \"${{EARTHDATA_TOKEN:-}}\" ]; then")?;
+        writeln!(out, "    auth=(--header \"Authorization: Bearer $EARTHDATA_TOKEN\")")?;
+        writeln!(out, "fi")?;
+        writeln!(out)?;
+        writeln!(out, "# fetch DIRECTORY GRANULE: download the files in DIRECTORY starting with GRANULE")?;
+        writeln!(out, "fetch() {{")?;
+        match downloader {
+            Downloader::Wget => {
+                writeln!(
+                    out,
+                    "    wget --no-verbose --recursive --level=1 --no-directories --no-parent -e robots=off \\"
+                )?;
+                writeln!(out, "        ${{auth[@]+\"${{auth[@]}}\"}} --accept \"$2*\" \"$1\"")?;
+            }
+            Downloader::Curl => {
+                // The granule is a shell pattern, as for wget --accept
+                writeln!(out, "    curl --silent --fail --location ${{auth[@]+\"${{auth[@]}}\"}} \"$1\" |")?;
+                writeln!(out, "        grep -o 'href=\"[^\"]*\"' | sed 's/^href=\"//; s/\"$//' | sort -u |")?;
+                writeln!(out, "        while read -r file; do")?;
+                writeln!(out, "            case \"${{file##*/}}\" in")?;
+                writeln!(
+                    out,
+                    "                $2*) curl --fail --location --remote-name ${{auth[@]+\"${{auth[@]}}\"}} \"$1${{file##*/}}\" ;;"
+                )?;
+                writeln!(out, "            esac")?;
+                writeln!(out, "        done")?;
+            }
+        }
+        writeln!(out, "}}")?;
+        writeln!(out)?;
+        for entry in self.entries.iter() {
+            writeln!(out, "fetch {} {}", shell_quote(&entry.directory), shell_quote(&entry.granule))?;
+        }
+        out.flush()
+    }
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orbital::{fixtures, OrbitNode};
+
+    #[test]
+    fn test_manifest() {
+        // 2013-11-05 16:03:43 UTC, the footprint spanning two granules
+        let cpa_time = 1383667423.0;
+        let event = TCSatPassEvent {
+            node: Some(OrbitNode::Descending),
+            orbit_number: Some(60090),
+            orbit_start: Some(cpa_time - 2791.0),
+            orbit_period: Some(5929.6),
+            footprint_start: Some(cpa_time - 150.0),
+            footprint_end: Some(cpa_time + 150.0),
+            ..fixtures::tc_event(cpa_time)
+        };
+        let products = vec![Product::preset("MYD021KM").unwrap(), Product::preset("AHI-H08").unwrap()];
+        let mut manifest = Manifest::new();
+        manifest.add_events(&products, &[event.clone(), event]);
+        let granules: Vec<&str> = manifest.entries().iter().map(|entry| entry.granule.as_str()).collect();
+        assert_eq!(granules, ["MYD021KM.A2013309.1600", "MYD021KM.A2013309.1605"]);
+        assert_eq!(
+            manifest.entries()[0].directory,
+            "https://ladsweb.modaps.eosdis.nasa.gov/archive/allData/61/MYD021KM/2013/309/"
+        );
+        assert_eq!(manifest.unarchived(), ["AHI-H08"]);
+
+        let mut script = Vec::new();
+        manifest.write_script(&mut script, Downloader::Wget).unwrap();
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.ends_with(
+            "fetch 'https://ladsweb.modaps.eosdis.nasa.gov/archive/allData/61/MYD021KM/2013/309/' \
+             'MYD021KM.A2013309.1605'\n"
+        ));
+    }
+}
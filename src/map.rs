@@ -0,0 +1,392 @@
+//! Pass geometry for GIS tools: satellite ground tracks, swath polygons and
+//! storm tracks as GeoJSON or KML
+//!
+//! Lines and polygons are split at the antimeridian and written with
+//! longitudes in [-180, 180], as both formats expect.
+
+use std::io::{self, Write};
+
+use geographiclib_rs::{DirectGeodesic, Geodesic, InverseGeodesic};
+use serde_json::{json, Value};
+
+use crate::orbital::TCSatPassEvent;
+use crate::output::{iso_fmt, EventSource};
+use crate::track::Track;
+
+/// Position in degrees as written, [longitude, latitude]
+type Coord = [f64; 2];
+
+/// Sampled target track
+struct TrackFeature {
+    id: String,
+    start_utc: f64,
+    stop_utc: f64,
+    /// (lat, lon) samples in time order
+    points: Vec<(f64, f64)>,
+}
+
+/// Ground track and swath of one pass
+struct PassFeature {
+    target: String,
+    satellite: String,
+    event: TCSatPassEvent,
+    ground_track: Vec<Vec<Coord>>,
+    swath: Vec<Vec<Coord>>,
+}
+
+/// Storm tracks and pass geometry collected for one map
+#[derive(Default)]
+pub struct MapLayers {
+    tracks: Vec<TrackFeature>,
+    passes: Vec<PassFeature>,
+}
+
+impl MapLayers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the track sampled every `step_sec` over its time range
+    pub fn add_track<T: Track + ?Sized>(&mut self, id: &str, track: &T, step_sec: f64) {
+        let Some((start_utc, stop_utc)) = track.time_range() else {
+            return;
+        };
+        let count = ((stop_utc - start_utc) / step_sec).ceil().max(0.0) as usize;
+        let points = (0..=count)
+            .map(|k| (start_utc + k as f64 * step_sec).min(stop_utc))
+            .filter_map(|t| track.position_at(t))
+            .collect();
+        self.tracks.push(TrackFeature { id: id.to_string(), start_utc, stop_utc, points });
+    }
+
+    /// Add the ground track of each pass and its swath `swath_km` to either
+    /// side. Geostationary scans have no ground track and are left out.
+    pub fn add_passes(&mut self, events: &[TCSatPassEvent], source: &EventSource, swath_km: f64) {
+        let geod = Geodesic::wgs84();
+        for event in events.iter().filter(|event| event.track.len() >= 2) {
+            let points: Vec<(f64, f64)> =
+                event.track.iter().map(|point| (point.latitude, point.longitude)).collect();
+            self.passes.push(PassFeature {
+                target: source.target.to_string(),
+                satellite: source.satellite.to_string(),
+                event: event.clone(),
+                ground_track: split_line(&points),
+                swath: split_polygon(&swath_ring(&geod, &points, swath_km)),
+            });
+        }
+    }
+
+    /// Write one GeoJSON FeatureCollection. Each pass gives a `ground_track`
+    /// and a `swath` feature, each target track a `track` feature.
+    pub fn write_geojson<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let mut features = Vec::new();
+        for track in self.tracks.iter() {
+            let properties = json!({
+                "feature": "track",
+                "storm_id": track.id,
+                "start": iso_fmt(track.start_utc),
+                "end": iso_fmt(track.stop_utc),
+            });
+            features.push(feature(track_geometry(&track.points), properties));
+        }
+        for pass in self.passes.iter() {
+            let event = &pass.event;
+            let properties = |kind: &str| {
+                json!({
+                    "feature": kind,
+                    "storm_id": pass.target,
+                    "satellite": pass.satellite,
+                    "time": iso_fmt(event.cpa_time),
+                    "distance_km": round(event.cpa_distance),
+                    "zenith_deg": round(event.sat_zenith),
+                    "intensity_kt": event.intensity,
+                    "node": event.node,
+                    "orbit_number": event.orbit_number,
+                })
+            };
+            let line = multi_geometry("LineString", &pass.ground_track, |part| json!(part));
+            features.push(feature(line, properties("ground_track")));
+            let polygon = multi_geometry("Polygon", &pass.swath, |ring| json!([ring]));
+            features.push(feature(polygon, properties("swath")));
+        }
+        let collection = json!({ "type": "FeatureCollection", "features": features });
+        serde_json::to_writer(&mut out, &collection)?;
+        writeln!(out)?;
+        out.flush()
+    }
+
+    /// Write one KML document with a folder of target tracks and a folder
+    /// with one placemark per pass holding its ground track and swath
+    pub fn write_kml<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+        writeln!(out, "<Document>")?;
+        writeln!(out, "<name>satpass</name>")?;
+        writeln!(out, r#"<Style id="track"><LineStyle><color>ff0000ff</color><width>2</width></LineStyle></Style>"#)?;
+        writeln!(out, r#"<Style id="pass"><LineStyle><color>ffffff00</color><width>1</width></LineStyle><PolyStyle><color>40ffff00</color></PolyStyle></Style>"#)?;
+
+        writeln!(out, "<Folder><name>Tracks</name>")?;
+        for track in self.tracks.iter() {
+            writeln!(out, "<Placemark><name>{}</name><styleUrl>#track</styleUrl>", xml_escape(&track.id))?;
+            writeln!(
+                out,
+                "<TimeSpan><begin>{}</begin><end>{}</end></TimeSpan>",
+                iso_fmt(track.start_utc),
+                iso_fmt(track.stop_utc)
+            )?;
+            match track.points.first() {
+                Some(&(lat, lon)) if is_fixed(&track.points) => {
+                    writeln!(out, "<Point><coordinates>{}</coordinates></Point>", kml_coords(&[to_coord(lat, lon)]))?;
+                }
+                _ => {
+                    writeln!(out, "<MultiGeometry>")?;
+                    for part in split_line(&track.points) {
+                        write_kml_line(&mut out, &part)?;
+                    }
+                    writeln!(out, "</MultiGeometry>")?;
+                }
+            }
+            writeln!(out, "</Placemark>")?;
+        }
+        writeln!(out, "</Folder>")?;
+
+        writeln!(out, "<Folder><name>Passes</name>")?;
+        for pass in self.passes.iter() {
+            let event = &pass.event;
+            let name = format!("{} {} {}", pass.satellite, pass.target, iso_fmt(event.cpa_time));
+            writeln!(out, "<Placemark><name>{}</name><styleUrl>#pass</styleUrl>", xml_escape(&name))?;
+            writeln!(out, "<TimeStamp><when>{}</when></TimeStamp>", iso_fmt(event.cpa_time))?;
+            let orbit = match (event.node, event.orbit_number) {
+                (Some(node), Some(orbit_number)) => format!(", {} node, orbit {}", node, orbit_number),
+                _ => String::new(),
+            };
+            writeln!(
+                out,
+                "<description>Distance: {:.0} km, zenith: {:.1}°{}</description>",
+                event.cpa_distance, event.sat_zenith, orbit
+            )?;
+            writeln!(out, "<MultiGeometry>")?;
+            for ring in pass.swath.iter() {
+                writeln!(
+                    out,
+                    "<Polygon><outerBoundaryIs><LinearRing><coordinates>{}</coordinates></LinearRing></outerBoundaryIs></Polygon>",
+                    kml_coords(ring)
+                )?;
+            }
+            for part in pass.ground_track.iter() {
+                write_kml_line(&mut out, part)?;
+            }
+            writeln!(out, "</MultiGeometry>")?;
+            writeln!(out, "</Placemark>")?;
+        }
+        writeln!(out, "</Folder>")?;
+        writeln!(out, "</Document>")?;
+        writeln!(out, "</kml>")?;
+        out.flush()
+    }
+}
+
+/// Polygon around the (lat, lon) ground track reaching `half_width_km` to
+/// either side, perpendicular to the direction of motion
+pub fn swath_ring(geod: &Geodesic, points: &[(f64, f64)], half_width_km: f64) -> Vec<(f64, f64)> {
+    let distance = half_width_km * 1000.0;
+    let mut right: Vec<(f64, f64)> = Vec::with_capacity(points.len());
+    let mut left: Vec<(f64, f64)> = Vec::with_capacity(points.len());
+    for (k, &(lat, lon)) in points.iter().enumerate() {
+        let azimuth = if k + 1 < points.len() {
+            let (next_lat, next_lon) = points[k + 1];
+            let (azi1, _, _): (f64, f64, f64) = geod.inverse(lat, lon, next_lat, next_lon);
+            azi1
+        } else {
+            let (prev_lat, prev_lon) = points[k - 1];
+            let (_, azi2, _): (f64, f64, f64) = geod.inverse(prev_lat, prev_lon, lat, lon);
+            azi2
+        };
+        right.push(geod.direct(lat, lon, azimuth + 90.0, distance));
+        left.push(geod.direct(lat, lon, azimuth - 90.0, distance));
+    }
+    right.extend(left.into_iter().rev());
+    right
+}
+
+/// Split a (lat, lon) line where it crosses the antimeridian
+pub fn split_line(points: &[(f64, f64)]) -> Vec<Vec<Coord>> {
+    let unwrapped = unwrap(points);
+    let mut parts = Vec::new();
+    let mut part: Vec<Coord> = Vec::new();
+    for (k, &[x, y]) in unwrapped.iter().enumerate() {
+        let world = world_index(x);
+        if k > 0 {
+            let [x0, y0] = unwrapped[k - 1];
+            let world0 = world_index(x0);
+            if world != world0 {
+                // Close this part on the meridian between the two points and
+                // open the next on the same meridian seen from the other side
+                let boundary = -180.0 + 360.0 * world.max(world0) as f64;
+                let y_cross = y0 + (y - y0) * (boundary - x0) / (x - x0);
+                part.push([round(boundary - 360.0 * world0 as f64), round(y_cross)]);
+                parts.push(std::mem::take(&mut part));
+                part.push([round(boundary - 360.0 * world as f64), round(y_cross)]);
+            }
+        }
+        part.push([round(x - 360.0 * world as f64), round(y)]);
+    }
+    if part.len() >= 2 {
+        parts.push(part);
+    }
+    parts
+}
+
+/// Split a (lat, lon) ring into closed, counterclockwise rings that do not
+/// cross the antimeridian. The ring must not enclose a pole.
+pub fn split_polygon(ring: &[(f64, f64)]) -> Vec<Vec<Coord>> {
+    let unwrapped = unwrap(ring);
+    let worlds = unwrapped.iter().map(|&[x, _]| world_index(x));
+    let (Some(first), Some(last)) = (worlds.clone().min(), worlds.max()) else {
+        return Vec::new();
+    };
+    let mut rings = Vec::new();
+    for world in first..=last {
+        let west = -180.0 + 360.0 * world as f64;
+        let clipped = clip(&clip(&unwrapped, west, true), west + 360.0, false);
+        if clipped.len() < 3 {
+            continue;
+        }
+        let mut shifted: Vec<Coord> = clipped
+            .iter()
+            .map(|&[x, y]| [round(x - 360.0 * world as f64), round(y)])
+            .collect();
+        if signed_area(&shifted) < 0.0 {
+            shifted.reverse();
+        }
+        shifted.push(shifted[0]);
+        rings.push(shifted);
+    }
+    rings
+}
+
+/// [lon, lat] with longitudes continued across the antimeridian, the first
+/// in [-180, 180)
+fn unwrap(points: &[(f64, f64)]) -> Vec<Coord> {
+    let mut coords: Vec<Coord> = Vec::with_capacity(points.len());
+    for &(lat, lon) in points.iter() {
+        let x = match coords.last() {
+            Some(&[x0, _]) => x0 + (lon - x0 + 180.0).rem_euclid(360.0) - 180.0,
+            None => (lon + 180.0).rem_euclid(360.0) - 180.0,
+        };
+        coords.push([x, lat]);
+    }
+    coords
+}
+
+/// Number of 360° turns between an unwrapped longitude and [-180, 180)
+fn world_index(x: f64) -> i64 {
+    ((x + 180.0) / 360.0).floor() as i64
+}
+
+/// Sutherland-Hodgman clip of a ring to the side of meridian `x0` east of it
+/// (`keep_east`) or west of it
+fn clip(ring: &[Coord], x0: f64, keep_east: bool) -> Vec<Coord> {
+    let inside = |&[x, _]: &Coord| if keep_east { x >= x0 } else { x <= x0 };
+    let mut clipped = Vec::with_capacity(ring.len() + 2);
+    for (k, current) in ring.iter().enumerate() {
+        let previous = &ring[(k + ring.len() - 1) % ring.len()];
+        if inside(current) != inside(previous) {
+            let [xp, yp] = *previous;
+            let [xc, yc] = *current;
+            clipped.push([x0, yp + (yc - yp) * (x0 - xp) / (xc - xp)]);
+        }
+        if inside(current) {
+            clipped.push(*current);
+        }
+    }
+    clipped
+}
+
+/// Twice the signed area of an open ring, positive if counterclockwise
+fn signed_area(ring: &[Coord]) -> f64 {
+    (0..ring.len())
+        .map(|k| {
+            let [x0, y0] = ring[k];
+            let [x1, y1] = ring[(k + 1) % ring.len()];
+            x0 * y1 - x1 * y0
+        })
+        .sum()
+}
+
+fn to_coord(lat: f64, lon: f64) -> Coord {
+    [round((lon + 180.0).rem_euclid(360.0) - 180.0), round(lat)]
+}
+
+// About 1 m, keeps the files small
+fn round(value: f64) -> f64 {
+    (value * 1e5).round() / 1e5
+}
+
+fn feature(geometry: Value, properties: Value) -> Value {
+    json!({ "type": "Feature", "geometry": geometry, "properties": properties })
+}
+
+/// Single geometry if there is one part, its multi-part form otherwise
+fn multi_geometry<F: Fn(&Vec<Coord>) -> Value>(kind: &str, parts: &[Vec<Coord>], coordinates: F) -> Value {
+    if parts.len() == 1 {
+        json!({ "type": kind, "coordinates": coordinates(&parts[0]) })
+    } else {
+        let coordinates: Vec<Value> = parts.iter().map(coordinates).collect();
+        json!({ "type": format!("Multi{}", kind), "coordinates": coordinates })
+    }
+}
+
+/// Whether the target does not move, such as a site
+fn is_fixed(points: &[(f64, f64)]) -> bool {
+    points.windows(2).all(|pair| pair[0] == pair[1])
+}
+
+/// Point for a fixed target, a line otherwise
+fn track_geometry(points: &[(f64, f64)]) -> Value {
+    match points.first() {
+        Some(&(lat, lon)) if is_fixed(points) => json!({ "type": "Point", "coordinates": to_coord(lat, lon) }),
+        _ => multi_geometry("LineString", &split_line(points), |part| json!(part)),
+    }
+}
+
+fn kml_coords(coords: &[Coord]) -> String {
+    coords.iter().map(|[x, y]| format!("{},{}", x, y)).collect::<Vec<_>>().join(" ")
+}
+
+fn write_kml_line<W: Write>(out: &mut W, coords: &[Coord]) -> io::Result<()> {
+    writeln!(
+        out,
+        "<LineString><tessellate>1</tessellate><coordinates>{}</coordinates></LineString>",
+        kml_coords(coords)
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_line() {
+        let parts = split_line(&[(0.0, 170.0), (10.0, 190.0), (20.0, 200.0)]);
+        assert_eq!(parts, vec![vec![[170.0, 0.0], [180.0, 5.0]], vec![[-180.0, 5.0], [-170.0, 10.0], [-160.0, 20.0]]]);
+        assert_eq!(split_line(&[(0.0, 10.0), (1.0, 20.0)]).len(), 1);
+    }
+
+    #[test]
+    fn test_split_polygon() {
+        let rings = split_polygon(&[(-10.0, 170.0), (-10.0, 190.0), (10.0, 190.0), (10.0, 170.0)]);
+        assert_eq!(rings.len(), 2);
+        for ring in rings.iter() {
+            assert_eq!(ring.first(), ring.last());
+            assert!(signed_area(&ring[..ring.len() - 1]) > 0.0);
+            assert!(ring.iter().all(|&[x, _]| (-180.0..=180.0).contains(&x)));
+        }
+        assert!(rings[0].iter().all(|&[x, _]| x >= 170.0));
+        assert!(rings[1].iter().all(|&[x, _]| x <= -170.0));
+    }
+}
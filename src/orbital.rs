@@ -1,11 +1,120 @@
+//! SGP4 orbits and closest approaches to ground targets
+
+use std::collections::HashMap;
+
 use geographiclib_rs::{Geodesic, InverseGeodesic};
-use predict_rs::observer::*;
-use predict_rs::orbit::*;
-use predict_rs::predict::*;
-use sgp4::{Constants, Elements};
+use serde::Serialize;
+use sgp4::{Constants, Elements, MinutesSinceEpoch};
 
 use crate::tle;
 
+const EARTH_RADIUS_KM_WGS84: f64 = 6378.137;
+const FLATTENING_FACTOR: f64 = 1.0 / 298.257_223_563;
+// Unix time of the J2000 epoch, 2000-01-01 12:00 UTC
+const J2000_UNIX_SECONDS: f64 = 946_728_000.0;
+// Mean motion bounds (rev/day) used to classify geostationary orbits
+const GEOSYNCHRONOUS_LOWER_MEAN_MOTION: f64 = 0.9;
+const GEOSYNCHRONOUS_UPPER_MEAN_MOTION: f64 = 1.1;
+// Earth rotation rate in rad/s
+const EARTH_ROTATION_RATE: f64 = 7.292_115e-5;
+// WGS84 gravitational parameter in km^3/s^2
+const EARTH_GM: f64 = 398_600.441_8;
+// The sub-point moves ~400 km per minute, so each minimum of the distance
+// to a target is bracketed by three samples this far apart
+const COARSE_STEP_SEC: f64 = 60.0;
+
+/// Failure to set up or evaluate an orbit. Satellites are identified by
+/// NORAD ID and element sets by their epoch.
+#[derive(Debug, Clone)]
+pub enum OrbitalError {
+    /// The TLE lines could not be parsed, by SGP4 if `source` is set, else
+    /// because line 1 has no valid epoch
+    InvalidTle { source: Option<sgp4::TleError> },
+    /// SGP4 rejected the elements at initialization
+    InvalidElements { norad_id: u64, epoch: f64, source: sgp4::ElementsError },
+    /// SGP4 could not propagate the elements to `time`
+    Propagation { norad_id: u64, epoch: f64, time: f64, source: sgp4::Error },
+    /// The satellite is below the surface of the Earth at `time`
+    Decayed { norad_id: u64, epoch: f64, time: f64 },
+    /// An iterative search around `time` did not converge
+    NonConvergence { time: f64 },
+    /// `time` is not covered by the ephemeris grid
+    OutsideEphemeris { time: f64 },
+    /// No element set could be used at `time`
+    NoElements { time: f64 },
+    /// The target position is unknown at `time`
+    UnknownPosition { time: f64 },
+}
+
+impl OrbitalError {
+    /// Whether the element set cannot be propagated to the requested time,
+    /// so an element set with another epoch may succeed
+    pub fn is_propagation_failure(&self) -> bool {
+        matches!(self, OrbitalError::Propagation { .. } | OrbitalError::Decayed { .. })
+    }
+}
+
+fn time_fmt(t_utc: f64) -> String {
+    chrono::DateTime::from_timestamp(t_utc.round() as i64, 0)
+        .map_or_else(|| t_utc.to_string(), |dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+impl std::fmt::Display for OrbitalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrbitalError::InvalidTle { source: Some(source) } => write!(f, "invalid TLE: {}", source),
+            OrbitalError::InvalidTle { source: None } => {
+                write!(f, "invalid TLE: no epoch in columns 19-32 of line 1")
+            }
+            OrbitalError::InvalidElements { norad_id, epoch, source } => write!(
+                f,
+                "invalid elements for {} at epoch {}: {}",
+                norad_id,
+                time_fmt(*epoch),
+                source
+            ),
+            OrbitalError::Propagation { norad_id, epoch, time, source } => write!(
+                f,
+                "propagation of {} from epoch {} failed at {}: {}",
+                norad_id,
+                time_fmt(*epoch),
+                time_fmt(*time),
+                source
+            ),
+            OrbitalError::Decayed { norad_id, epoch, time } => write!(
+                f,
+                "{} decayed by {} propagating from epoch {}",
+                norad_id,
+                time_fmt(*time),
+                time_fmt(*epoch)
+            ),
+            OrbitalError::NonConvergence { time } => {
+                write!(f, "search did not converge near {}", time_fmt(*time))
+            }
+            OrbitalError::OutsideEphemeris { time } => {
+                write!(f, "{} is outside the ephemeris", time_fmt(*time))
+            }
+            OrbitalError::NoElements { time } => {
+                write!(f, "no usable element set at {}", time_fmt(*time))
+            }
+            OrbitalError::UnknownPosition { time } => {
+                write!(f, "target position unknown at {}", time_fmt(*time))
+            }
+        }
+    }
+}
+
+impl std::error::Error for OrbitalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OrbitalError::InvalidTle { source } => source.as_ref().map(|source| source as _),
+            OrbitalError::InvalidElements { source, .. } => Some(source),
+            OrbitalError::Propagation { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
 fn geodesic_distance(
     geod: &Geodesic,
     lat1: f64,
@@ -17,222 +126,1430 @@ fn geodesic_distance(
     s12 / 1000.0 // Convert meters to kilometers
 }
 
-fn observe_orbit(oe: &ObserverElements, time: f64) -> (PredictPosition, PredictObservation) {
-    let orbit = predict_orbit(oe.elements, oe.constants, time).unwrap();
-    let obs = predict_observe_orbit(oe.observer, &orbit);
-    (orbit, obs)
-}
-
-fn elevation_derivative(oe: &ObserverElements, time: f64) -> f64 {
-    let (_, obs) = observe_orbit(oe, time);
-    obs.elevation_rate
-}
-
-// Modified from predict_rs original function to return time of max elevation
-fn find_max_elevation(
-    oe: &ObserverElements,
-    lower_time: f64,
-    upper_time: f64,
-) -> (f64, f64, PredictPosition) {
-    let mut iteration = 0u32;
-    let mut lower_time = lower_time;
-    let mut upper_time = upper_time;
-    let mut lower_deriv = elevation_derivative(oe, lower_time);
-    let mut upper_deriv = elevation_derivative(oe, upper_time);
-    let mut max_ele_time_candidate = (upper_time + lower_time) / 2.0;
-    let (mut orbit, mut obs) = observe_orbit(oe, max_ele_time_candidate);
-    while ((lower_time - upper_time).abs() > 1e-6) && (iteration < 10000) {
-        // calculate derivatives for candidate
-        let candidate_deriv = obs.elevation_rate;
-
-        //check whether derivative has changed sign
-        if candidate_deriv * lower_deriv < 0.0 {
-            upper_time = max_ele_time_candidate;
-            upper_deriv = candidate_deriv;
-        } else if candidate_deriv * upper_deriv < 0.0 {
-            lower_time = max_ele_time_candidate;
-            lower_deriv = candidate_deriv;
-        } else {
+// Central angle in radians between two points given in degrees
+fn great_circle_angle(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (lon2 - lon1).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * h.sqrt().min(1.0).asin()
+}
+
+// WGS84 geodetic coordinates (radians, km) to ECEF in km
+fn geodetic_to_ecef(lat: f64, lon: f64, alt: f64) -> [f64; 3] {
+    let e2 = FLATTENING_FACTOR * (2.0 - FLATTENING_FACTOR);
+    let n = EARTH_RADIUS_KM_WGS84 / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+    [
+        (n + alt) * lat.cos() * lon.cos(),
+        (n + alt) * lat.cos() * lon.sin(),
+        (n * (1.0 - e2) + alt) * lat.sin(),
+    ]
+}
+
+// ECEF in km to WGS84 geodetic latitude, longitude (radians) and altitude (km)
+fn ecef_to_geodetic(ecef: [f64; 3]) -> (f64, f64, f64) {
+    let e2 = FLATTENING_FACTOR * (2.0 - FLATTENING_FACTOR);
+    let r = ecef[0].hypot(ecef[1]);
+    let lon = ecef[1].atan2(ecef[0]);
+    let mut lat = ecef[2].atan2(r);
+    let mut c = 1.0;
+    // Converges to 1e-10 in a handful of iterations for any point off the axis
+    for _ in 0..50 {
+        let phi = lat;
+        c = 1.0 / (1.0 - e2 * phi.sin().powi(2)).sqrt();
+        lat = (ecef[2] + EARTH_RADIUS_KM_WGS84 * c * e2 * phi.sin()).atan2(r);
+        if (lat - phi).abs() < 1e-10 {
             break;
         }
-        iteration += 1;
-        max_ele_time_candidate = (upper_time + lower_time) / 2.0;
-        (orbit, obs) = observe_orbit(oe, max_ele_time_candidate);
     }
+    let alt = r / lat.cos() - EARTH_RADIUS_KM_WGS84 * c;
+    (lat, lon, alt)
+}
 
-    let max_elev = obs.elevation.to_degrees();
-    (max_elev, max_ele_time_candidate, orbit)
+// Rotate a TEME vector into the Earth-fixed frame at the given unix time
+fn teme_to_ecef(teme: [f64; 3], t_utc: f64) -> [f64; 3] {
+    let years_since_j2000 = (t_utc - J2000_UNIX_SECONDS) / (86400.0 * 365.25);
+    let gmst = sgp4::iau_epoch_to_sidereal_time(years_since_j2000);
+    let (sin_g, cos_g) = gmst.sin_cos();
+    [
+        cos_g * teme[0] + sin_g * teme[1],
+        -sin_g * teme[0] + cos_g * teme[1],
+        teme[2],
+    ]
 }
 
-fn build_passes(
-    oe: &ObserverElements,
-    start_utc: f64,
-    stop_utc: f64,
-    include_max_elevation: bool,
-) -> Passes {
-    let (_, obs) = observe_orbit(oe, start_utc);
-    let satellite_el = obs.elevation.to_degrees();
-    let mut currtime = start_utc;
-    let mut passes = vec![];
-    let min_elev_deg = oe.observer.min_elevation;
-    let coarse_step_sec = 10.0;
-    let fine_step_sec = 1.0;
-    let band_deg = 5.0;
-
-    if satellite_el.abs() >= min_elev_deg {
-        // Already in a pass, find AOS by going backwards in time
-        let (_, real_aos) = step_pass(oe, currtime, &StepPassDirection::NegativeDirection).unwrap();
-        currtime = real_aos - 1.0;
-    }
-    'outer: loop {
-        let mut pass = Pass {
-            aos: None,
-            los: None,
-            satellite_position_at_aos: None,
-            satellite_position_at_los: None,
-            max_elevation: None,
-        };
-        // rough time step until AOS
-        loop {
-            if currtime >= stop_utc {
-                break 'outer;
-            }
-            let (_, obs) = observe_orbit(oe, currtime);
-            let satellite_el = obs.elevation.to_degrees();
-            if satellite_el >= min_elev_deg && obs.elevation_rate > 0.0 {
-                currtime -= fine_step_sec;
-                let (satpos, observation, _) =
-                    refine_obs_elevation(oe, currtime, &RefineMode::AOS).unwrap();
-                pass.aos = Some(observation);
-                pass.satellite_position_at_aos = Some(satpos);
-                currtime += fine_step_sec;
-                break;
-            }
-            let step = if (satellite_el - min_elev_deg).abs() > band_deg {
-                coarse_step_sec
+// Satellite zenith angle in degrees seen from a ground point with ECEF position
+// `target` and geodetic unit normal `up`
+fn zenith_angle(target: [f64; 3], up: [f64; 3], sat: [f64; 3]) -> f64 {
+    let to_sat = [sat[0] - target[0], sat[1] - target[1], sat[2] - target[2]];
+    let range = to_sat.iter().map(|v| v * v).sum::<f64>().sqrt();
+    let cos_zenith = (0..3).map(|k| up[k] * to_sat[k]).sum::<f64>() / range;
+    cos_zenith.clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+// Angle in degrees [0, 360) travelled from the ascending node on a near-circular
+// orbit. Earth rotation leaves the z components of position and velocity
+// unchanged, and the inertial speed is taken as the circular speed.
+fn argument_of_latitude(state: &SatState) -> f64 {
+    let radius = state.ecef.iter().map(|v| v * v).sum::<f64>().sqrt();
+    let speed = (EARTH_GM / radius).sqrt();
+    (state.ecef[2] / radius)
+        .atan2(state.velocity[2] / speed)
+        .to_degrees()
+        .rem_euclid(360.0)
+}
+
+// Geodetic unit normal at a point given in degrees
+fn local_up(latitude: f64, longitude: f64) -> [f64; 3] {
+    let (lat, lon) = (latitude.to_radians(), longitude.to_radians());
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+// Brent's method for the minimum of `f` bracketed in [a, b], returns (x, f(x))
+fn brent_minimize<F: Fn(f64) -> Result<f64, OrbitalError>>(
+    f: F,
+    a: f64,
+    b: f64,
+    tol: f64,
+) -> Result<(f64, f64), OrbitalError> {
+    const GOLDEN: f64 = 0.381_966_011_250_105;
+    let (mut a, mut b) = (a, b);
+    let mut x = a + GOLDEN * (b - a);
+    let (mut w, mut v) = (x, x);
+    let mut fx = f(x)?;
+    let (mut fw, mut fv) = (fx, fx);
+    let mut d: f64 = 0.0;
+    let mut e: f64 = 0.0;
+    for _ in 0..100 {
+        let xm = 0.5 * (a + b);
+        let tol1 = tol + 1e-10 * x.abs();
+        let tol2 = 2.0 * tol1;
+        if (x - xm).abs() <= tol2 - 0.5 * (b - a) {
+            return Ok((x, fx));
+        }
+        let mut golden_step = true;
+        if e.abs() > tol1 {
+            // Try a parabolic fit through x, w and v
+            let r = (x - w) * (fx - fv);
+            let mut q = (x - v) * (fx - fw);
+            let mut p = (x - v) * q - (x - w) * r;
+            q = 2.0 * (q - r);
+            if q > 0.0 {
+                p = -p;
+            }
+            q = q.abs();
+            if p.abs() < (0.5 * q * e).abs() && p > q * (a - x) && p < q * (b - x) {
+                e = d;
+                d = p / q;
+                let u = x + d;
+                if u - a < tol2 || b - u < tol2 {
+                    d = tol1.copysign(xm - x);
+                }
+                golden_step = false;
+            }
+        }
+        if golden_step {
+            e = if x >= xm { a - x } else { b - x };
+            d = GOLDEN * e;
+        }
+        let u = if d.abs() >= tol1 { x + d } else { x + tol1.copysign(d) };
+        let fu = f(u)?;
+        if fu <= fx {
+            if u >= x {
+                a = x;
             } else {
-                fine_step_sec
-            };
-            currtime += step;
-        }
-        if pass.aos.is_none() {
-            println!("Shouldn't be here");
-        }
-        // now find LOS
-        loop {
-            let (_, obs) = observe_orbit(oe, currtime);
-            let satellite_el = obs.elevation.to_degrees();
-            if satellite_el <= min_elev_deg && obs.elevation_rate < 0.0 {
-                currtime -= fine_step_sec;
-                let (satpos, observation, _) =
-                    refine_obs_elevation(oe, currtime, &RefineMode::LOS).unwrap();
-                pass.los = Some(observation);
-                pass.satellite_position_at_los = Some(satpos);
-                currtime += fine_step_sec;
-                break;
-            }
-            let step = if (satellite_el - min_elev_deg).abs() > band_deg {
-                coarse_step_sec
+                b = x;
+            }
+            (v, fv) = (w, fw);
+            (w, fw) = (x, fx);
+            (x, fx) = (u, fu);
+        } else {
+            if u < x {
+                a = u;
             } else {
-                fine_step_sec
-            };
-            currtime += step;
-            if currtime >= stop_utc {
-                break;
+                b = u;
             }
-        }
-        if pass.aos.is_some() && pass.los.is_some() {
-            if include_max_elevation {
-                let (maxel_obs, _, _) = find_max_elevation(
-                    oe,
-                    pass.aos.as_ref().expect("already checked").time,
-                    pass.los.as_ref().expect("already checked").time,
-                );
-                pass.max_elevation = Some(maxel_obs);
+            if fu <= fw || w == x {
+                (v, fv) = (w, fw);
+                (w, fw) = (u, fu);
+            } else if fu <= fv || v == x || v == w {
+                (v, fv) = (u, fu);
             }
         }
-        passes.push(pass);
     }
-    Passes { passes }
+    Err(OrbitalError::NonConvergence { time: x })
+}
+
+/// Mean local solar time in hours [0, 24) at the given longitude
+pub fn local_solar_time(t_utc: f64, longitude: f64) -> f64 {
+    let utc_hours = t_utc.rem_euclid(86400.0) / 3600.0;
+    (utc_hours + longitude / 15.0).rem_euclid(24.0)
+}
+
+/// Propagated satellite state
+#[derive(Debug, Clone)]
+pub struct SatState {
+    /// Earth-fixed velocity in km/s
+    pub velocity: [f64; 3],
+    /// Earth-fixed position in km
+    pub ecef: [f64; 3],
+    /// Sub-point latitude in degrees
+    pub latitude: f64,
+    /// Sub-point longitude in degrees east, [0, 360)
+    pub longitude: f64,
+}
+
+impl SatState {
+    fn from_ecef(ecef: [f64; 3], velocity: [f64; 3]) -> Self {
+        let (lat, lon, _) = ecef_to_geodetic(ecef);
+        SatState {
+            velocity,
+            ecef,
+            latitude: lat.to_degrees(),
+            longitude: lon.to_degrees().rem_euclid(360.0),
+        }
+    }
+}
+
+/// Direction of the satellite across the equator plane at CPA
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrbitNode {
+    Ascending,
+    Descending,
+}
+
+impl OrbitNode {
+    /// Classify from the velocity, northbound motion is ascending
+    fn from_velocity(velocity_z: f64) -> Self {
+        if velocity_z >= 0.0 {
+            OrbitNode::Ascending
+        } else {
+            OrbitNode::Descending
+        }
+    }
 }
 
+impl std::fmt::Display for OrbitNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrbitNode::Ascending => write!(f, "ascending"),
+            OrbitNode::Descending => write!(f, "descending"),
+        }
+    }
+}
+
+/// Storm quadrant relative to the direction of motion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StormQuadrant {
+    FrontRight,
+    RearRight,
+    RearLeft,
+    FrontLeft,
+}
+
+impl StormQuadrant {
+    /// Quadrant of a bearing measured clockwise from the storm heading
+    pub fn from_relative_bearing(bearing: f64) -> Self {
+        match bearing.rem_euclid(360.0) {
+            b if b < 90.0 => StormQuadrant::FrontRight,
+            b if b < 180.0 => StormQuadrant::RearRight,
+            b if b < 270.0 => StormQuadrant::RearLeft,
+            _ => StormQuadrant::FrontLeft,
+        }
+    }
+}
+
+impl std::fmt::Display for StormQuadrant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StormQuadrant::FrontRight => write!(f, "front-right"),
+            StormQuadrant::RearRight => write!(f, "rear-right"),
+            StormQuadrant::RearLeft => write!(f, "rear-left"),
+            StormQuadrant::FrontLeft => write!(f, "front-left"),
+        }
+    }
+}
+
+/// Sub-satellite point in storm-relative coordinates
+#[derive(Debug, Clone, Serialize)]
+pub struct StormRelativePoint {
+    pub time: f64,
+    /// Distance from the storm center in km
+    pub range: f64,
+    /// Bearing from the storm center, degrees clockwise from north
+    pub bearing: f64,
+    /// Bearing clockwise from the storm heading, None if the motion is unknown
+    pub relative_bearing: Option<f64>,
+    pub quadrant: Option<StormQuadrant>,
+    /// Sub-satellite point in degrees, longitude east [0, 360)
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Revolution of a satellite. Revolutions start at the ascending node and are
+/// numbered on from the revolution number of the TLE.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Revolution {
+    pub number: u64,
+    /// Time of the ascending node that starts the revolution, unix seconds
+    pub ascending_node: f64,
+    /// Orbital period in seconds
+    pub period: f64,
+}
+
+/// Closest approach of a satellite to a ground target
 #[derive(Debug, Clone)]
 pub struct SatPassEvent {
+    /// Time of closest approach, unix seconds
     pub cpa_time: f64,
+    /// Distance from the target to the sub-satellite point in km
     pub cpa_distance: f64,
+    /// Satellite elevation at the target in degrees
     pub elevation: f64,
+    /// None for geostationary scans
+    pub node: Option<OrbitNode>,
+    /// Revolution number at CPA, counted at ascending nodes, None for
+    /// geostationary scans
+    pub orbit_number: Option<u64>,
+    /// Ascending node that starts the revolution, unix seconds, None for
+    /// geostationary scans
+    pub orbit_start: Option<f64>,
+    /// Orbital period in seconds, None for geostationary scans
+    pub orbit_period: Option<f64>,
+    /// Local solar time at the target at CPA, in hours
+    pub local_solar_time: f64,
+    /// Local solar time of the satellite equator crossing on this orbit node, in hours
+    pub equator_crossing_time: f64,
+    /// Start of the geostationary scan that imaged the target
+    pub scan_start: Option<f64>,
+    /// Part of the disk that scan covered
+    pub scan_area: Option<ScanArea>,
 }
 
-#[derive(Debug, Clone)]
+/// Pass over a storm, see `SatPassEvent` for the common fields
+#[derive(Debug, Clone, Serialize)]
 pub struct TCSatPassEvent {
     pub cpa_time: f64,
     pub cpa_distance: f64,
+    /// Satellite zenith angle at the storm center in degrees
     pub sat_zenith: f64,
-    pub intensity: f64,
+    /// Storm intensity at CPA in kt, None if the track has no intensity
+    pub intensity: Option<f64>,
+    /// Storm center at CPA in degrees, longitude east [0, 360)
+    pub storm_latitude: f64,
+    pub storm_longitude: f64,
+    /// NORAD ID and epoch of the element set the pass was computed from
+    pub norad_id: u64,
+    pub tle_epoch: f64,
+    pub node: Option<OrbitNode>,
+    pub orbit_number: Option<u64>,
+    pub orbit_start: Option<f64>,
+    pub orbit_period: Option<f64>,
+    pub local_solar_time: f64,
+    pub equator_crossing_time: f64,
+    pub scan_start: Option<f64>,
+    pub scan_area: Option<ScanArea>,
+    /// Times the swath starts and stops covering the storm within the
+    /// footprint radius, None if no radius was searched or for geostationary scans
+    pub footprint_start: Option<f64>,
+    pub footprint_end: Option<f64>,
+    /// Sub-satellite point at CPA relative to the storm, None for geostationary scans
+    pub cpa_relative: Option<StormRelativePoint>,
+    /// Sub-satellite track around CPA relative to the storm
+    pub track: Vec<StormRelativePoint>,
+}
+
+impl TCSatPassEvent {
+    /// Revolution the satellite was on at CPA, None for geostationary scans
+    pub fn revolution(&self) -> Option<Revolution> {
+        Some(Revolution {
+            number: self.orbit_number?,
+            ascending_node: self.orbit_start?,
+            period: self.orbit_period?,
+        })
+    }
+}
+
+/// Merge repeated finds of the same pass, which happen when search windows
+/// overlap, and sort the events by CPA time. Events of one satellite on the
+/// same node with CPA times within half an orbital period, or geostationary
+/// events of the same scan, are one pass; of these the one computed from the
+/// elements nearest in time is kept. Revolution numbers are not compared, as
+/// element sets of different epochs can count the same pass one apart.
+pub fn dedup_passes(events: &mut Vec<TCSatPassEvent>) {
+    let mut groups: HashMap<_, Vec<TCSatPassEvent>> = HashMap::new();
+    for event in events.drain(..) {
+        let scan = event.scan_start.map(f64::to_bits).zip(event.scan_area);
+        groups.entry((event.norad_id, event.node, scan)).or_default().push(event);
+    }
+    let age = |event: &TCSatPassEvent| (event.cpa_time - event.tle_epoch).abs();
+    for mut group in groups.into_values() {
+        group.sort_by(|a, b| a.cpa_time.total_cmp(&b.cpa_time));
+        // CPA time of the first find of the pass being merged
+        let mut first_time: Option<f64> = None;
+        for event in group {
+            // Passes on one node are an orbital period apart, and the scan
+            // start already tells geostationary passes apart
+            let tolerance = event.orbit_period.map_or(f64::INFINITY, |period| period / 2.0);
+            match (first_time, events.last_mut()) {
+                (Some(first), Some(last)) if event.cpa_time - first <= tolerance => {
+                    if age(&event) < age(last) {
+                        *last = event;
+                    }
+                }
+                _ => {
+                    first_time = Some(event.cpa_time);
+                    events.push(event);
+                }
+            }
+        }
+    }
+    events.sort_by(|a, b| a.cpa_time.total_cmp(&b.cpa_time));
 }
 
+/// Part of the disk a geostationary scan covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanArea {
+    FullDisk,
+    /// Target-area (rapid-scan) sector
+    Sector,
+}
+
+impl std::fmt::Display for ScanArea {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanArea::FullDisk => write!(f, "full_disk"),
+            ScanArea::Sector => write!(f, "sector"),
+        }
+    }
+}
+
+/// Nominal repeat cycle of a geostationary imager
+#[derive(Debug, Clone, Serialize)]
+pub struct GeoScanSchedule {
+    /// Time between scan starts, aligned to 00 UTC
+    pub interval_sec: f64,
+    /// Time taken to scan the full frame from one edge to the other
+    pub duration_sec: f64,
+    /// SEVIRI-style imagers scan from south to north
+    pub south_to_north: bool,
+    /// Sector scanned on its own cycle between the full frames
+    pub sector: Option<GeoSector>,
+}
+
+impl GeoScanSchedule {
+    // Fraction of a frame spanning `south` to `north` scanned before reaching
+    // `position`, in N-S scan angle or latitude
+    fn line_fraction(&self, position: f64, south: f64, north: f64) -> f64 {
+        let fraction = ((north - position) / (north - south)).clamp(0.0, 1.0);
+        if self.south_to_north {
+            1.0 - fraction
+        } else {
+            fraction
+        }
+    }
+}
+
+/// Target-area sector of a geostationary imager, in the scan direction of
+/// its schedule
+#[derive(Debug, Clone, Serialize)]
+pub struct GeoSector {
+    /// Time between sector scan starts, aligned to 00 UTC
+    pub interval_sec: f64,
+    /// Time taken to scan the sector from one edge to the other
+    pub duration_sec: f64,
+    /// Latitude bounds in degrees
+    pub south: f64,
+    pub north: f64,
+    /// Longitude bounds in degrees east; the sector runs east from `west` to
+    /// `east` and may cross the antimeridian
+    pub west: f64,
+    pub east: f64,
+}
+
+impl GeoSector {
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        (self.south..=self.north).contains(&latitude)
+            && (longitude - self.west).rem_euclid(360.0) <= (self.east - self.west).rem_euclid(360.0)
+    }
+}
+
+/// Mean elements of a TLE and the orbit size they give
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MeanElements {
+    pub norad_id: u64,
+    /// Unix seconds
+    pub epoch: f64,
+    pub inclination_deg: f64,
+    pub eccentricity: f64,
+    /// Revolutions per day
+    pub mean_motion: f64,
+    pub period_sec: f64,
+    /// Heights above the equatorial radius in km
+    pub perigee_km: f64,
+    pub apogee_km: f64,
+}
+
+/// SGP4 orbit of one element set
 pub struct Orbital{
     elements: Elements,
     constants: Constants,
+    epoch_utc: f64,
+    geod: Geodesic,
+    // Revolutions beyond the 5-digit TLE revolution field
+    revolution_offset: u64,
 }
 
 impl Orbital {
-    pub fn new(tle: &tle::TLE) -> Self {
+    /// Initialize SGP4 from a TLE
+    pub fn new(tle: &tle::TLE) -> Result<Self, OrbitalError> {
         let elements = Elements::from_tle(
             None,
             tle.line1.as_bytes(),
             tle.line2.as_bytes(),
-        ).expect("Failed to parse TLE");
-        let constants = Constants::from_elements(&elements).unwrap();
-        Self {
+        ).map_err(|source| OrbitalError::InvalidTle { source: Some(source) })?;
+        let epoch_utc = elements.datetime.and_utc().timestamp_micros() as f64 / 1_000_000.0;
+        let constants = Constants::from_elements(&elements).map_err(|source| {
+            OrbitalError::InvalidElements { norad_id: elements.norad_id, epoch: epoch_utc, source }
+        })?;
+        Ok(Self {
             elements,
             constants,
+            epoch_utc,
+            geod: Geodesic::wgs84(),
+            revolution_offset: 0,
+        })
+    }
+
+    /// State at `time` in unix seconds
+    pub fn propagate(&self, time: f64) -> Result<SatState, OrbitalError> {
+        let prediction = self
+            .constants
+            .propagate(MinutesSinceEpoch((time - self.epoch_utc) / 60.0))
+            .map_err(|source| OrbitalError::Propagation {
+                norad_id: self.elements.norad_id,
+                epoch: self.epoch_utc,
+                time,
+                source,
+            })?;
+        let ecef = teme_to_ecef(prediction.position, time);
+        let (lat, lon, alt) = ecef_to_geodetic(ecef);
+        if alt < 0.0 {
+            return Err(OrbitalError::Decayed {
+                norad_id: self.elements.norad_id,
+                epoch: self.epoch_utc,
+                time,
+            });
+        }
+        // Rotate the velocity and remove the motion of the frame, w x r
+        let rotated = teme_to_ecef(prediction.velocity, time);
+        let velocity = [
+            rotated[0] + EARTH_ROTATION_RATE * ecef[1],
+            rotated[1] - EARTH_ROTATION_RATE * ecef[0],
+            rotated[2],
+        ];
+        Ok(SatState {
+            velocity,
+            ecef,
+            latitude: lat.to_degrees(),
+            longitude: lon.to_degrees().rem_euclid(360.0),
+        })
+    }
+
+    // Mean revolution count at `time` from the TLE epoch rev number, the mean
+    // argument of latitude at epoch and the mean motion
+    fn revolutions(&self, time: f64) -> f64 {
+        let days = (time - self.epoch_utc) / 86400.0;
+        (self.revolution_offset + self.elements.revolution_number) as f64
+            + (self.elements.argument_of_perigee + self.elements.mean_anomaly) / 360.0
+            + (self.elements.mean_motion + self.elements.mean_motion_dot * days) * days
+    }
+
+    /// Revolution the satellite is on at `time`
+    pub fn revolution_at(&self, time: f64) -> Result<Revolution, OrbitalError> {
+        self.revolution_with(time, &|time| self.propagate(time))
+    }
+
+    // As `revolution_at`, locating the ascending node on the states of `state_at`
+    fn revolution_with<S>(&self, time: f64, state_at: &S) -> Result<Revolution, OrbitalError>
+    where
+        S: Fn(f64) -> Result<SatState, OrbitalError>,
+    {
+        let period = 86400.0 / self.elements.mean_motion;
+        let angle = argument_of_latitude(&state_at(time)?);
+        // The mean count is off by a fraction of a revolution at most, the
+        // position on the orbit fixes the whole number
+        let number = (self.revolutions(time) - angle / 360.0).round();
+        let mut ascending_node = time - angle / 360.0 * period;
+        for _ in 0..3 {
+            let offset = (argument_of_latitude(&state_at(ascending_node)?) + 180.0)
+                .rem_euclid(360.0)
+                - 180.0;
+            ascending_node -= offset / 360.0 * period;
+        }
+        Ok(Revolution {
+            number: number.max(0.0) as u64,
+            ascending_node,
+            period,
+        })
+    }
+
+    /// Count revolutions from `offset` more than the TLE revolution field,
+    /// which wraps from 99999 to 0
+    pub fn add_revolution_offset(&mut self, offset: u64) {
+        self.revolution_offset += offset;
+    }
+
+    /// NORAD catalog number of the satellite
+    pub fn norad_id(&self) -> u64 {
+        self.elements.norad_id
+    }
+
+    /// Epoch of the element set, unix seconds
+    pub fn epoch(&self) -> f64 {
+        self.epoch_utc
+    }
+
+    /// Mean elements with the semi-major axis from the mean motion
+    pub fn mean_elements(&self) -> MeanElements {
+        let period_sec = 86400.0 / self.elements.mean_motion;
+        let angular_rate = 2.0 * std::f64::consts::PI / period_sec;
+        let semi_major_axis = (EARTH_GM / (angular_rate * angular_rate)).cbrt();
+        MeanElements {
+            norad_id: self.elements.norad_id,
+            epoch: self.epoch_utc,
+            inclination_deg: self.elements.inclination,
+            eccentricity: self.elements.eccentricity,
+            mean_motion: self.elements.mean_motion,
+            period_sec,
+            perigee_km: semi_major_axis * (1.0 - self.elements.eccentricity) - EARTH_RADIUS_KM_WGS84,
+            apogee_km: semi_major_axis * (1.0 + self.elements.eccentricity) - EARTH_RADIUS_KM_WGS84,
+        }
+    }
+
+    /// Satellite zenith angle in degrees at `time` seen from a point at
+    /// `altitude_km` above the ellipsoid
+    pub fn zenith_angle_at(
+        &self,
+        time: f64,
+        latitude: f64,
+        longitude: f64,
+        altitude_km: f64,
+    ) -> Result<f64, OrbitalError> {
+        let state = self.propagate(time)?;
+        let target = geodetic_to_ecef(latitude.to_radians(), longitude.to_radians(), altitude_km);
+        Ok(zenith_angle(target, local_up(latitude, longitude), state.ecef))
+    }
+
+    /// Whether the mean motion is about one revolution per day
+    pub fn is_geostationary(&self) -> bool {
+        (GEOSYNCHRONOUS_LOWER_MEAN_MOTION..=GEOSYNCHRONOUS_UPPER_MEAN_MOTION)
+            .contains(&self.elements.mean_motion)
+    }
+
+    /// Scans of a geostationary imager starting in the interval that view the
+    /// target below `max_zenith`, full frames and sector scans covering the
+    /// target, timed at the line over the target latitude and sorted by that
+    /// time. `position_at` gives the target (latitude, longitude) in degrees at a
+    /// time, the scans stop at the first time it gives None.
+    pub fn get_geo_scans<F: Fn(f64) -> Option<(f64, f64)>>(
+        &self,
+        start_utc: f64,
+        interval_sec: f64,
+        position_at: F,
+        schedule: &GeoScanSchedule,
+        max_zenith: f64,
+    ) -> Result<Vec<SatPassEvent>, OrbitalError> {
+        let mut scan_events = Vec::new();
+        let full_disk = (ScanArea::FullDisk, schedule.interval_sec);
+        let sector = schedule.sector.as_ref().map(|sector| (ScanArea::Sector, sector.interval_sec));
+        for (area, cycle_sec) in [full_disk].into_iter().chain(sector) {
+            let mut scan_start = (start_utc / cycle_sec).ceil() * cycle_sec;
+            while scan_start < start_utc + interval_sec {
+                // The storm moves a few km at most during one scan
+                let Some((latitude, longitude)) = position_at(scan_start) else {
+                    break;
+                };
+                if let Some(event) = self.geo_scan(scan_start, area, latitude, longitude, schedule, max_zenith)? {
+                    scan_events.push(event);
+                }
+                scan_start += cycle_sec;
+            }
         }
+        scan_events.sort_by(|a, b| a.cpa_time.total_cmp(&b.cpa_time));
+        Ok(scan_events)
+    }
+
+    // Scan of `area` starting at `scan_start` if it sees the target within
+    // `max_zenith`, timed when the scan line reaches the target
+    fn geo_scan(
+        &self,
+        scan_start: f64,
+        area: ScanArea,
+        latitude: f64,
+        longitude: f64,
+        schedule: &GeoScanSchedule,
+        max_zenith: f64,
+    ) -> Result<Option<SatPassEvent>, OrbitalError> {
+        let target = geodetic_to_ecef(latitude.to_radians(), longitude.to_radians(), 0.0);
+        let up = local_up(latitude, longitude);
+        let state = self.propagate(scan_start)?;
+        let zenith = zenith_angle(target, up, state.ecef);
+        if zenith > max_zenith {
+            return Ok(None);
+        }
+        let line_offset = match area {
+            ScanArea::FullDisk => {
+                let sat = state.ecef;
+                let to_target = [target[0] - sat[0], target[1] - sat[1], target[2] - sat[2]];
+                let range = to_target.iter().map(|v| v * v).sum::<f64>().sqrt();
+                let sat_radius = sat.iter().map(|v| v * v).sum::<f64>().sqrt();
+                let max_angle = (EARTH_RADIUS_KM_WGS84 / sat_radius).asin();
+                // Angle of the target north of the satellite boresight
+                let ns_angle = (to_target[2] / range).asin();
+                schedule.line_fraction(ns_angle, -max_angle, max_angle) * schedule.duration_sec
+            }
+            ScanArea::Sector => {
+                let Some(sector) = schedule.sector.as_ref().filter(|sector| sector.contains(latitude, longitude))
+                else {
+                    return Ok(None);
+                };
+                schedule.line_fraction(latitude, sector.south, sector.north) * sector.duration_sec
+            }
+        };
+        let line_time = scan_start + line_offset;
+        Ok(Some(SatPassEvent {
+            cpa_time: line_time,
+            cpa_distance: geodesic_distance(
+                &self.geod,
+                latitude,
+                longitude,
+                state.latitude,
+                state.longitude,
+            ),
+            elevation: 90.0 - zenith,
+            node: None,
+            orbit_number: None,
+            orbit_start: None,
+            orbit_period: None,
+            local_solar_time: local_solar_time(line_time, longitude),
+            // The satellite never leaves the equator
+            equator_crossing_time: local_solar_time(line_time, state.longitude),
+            scan_start: Some(scan_start),
+            scan_area: Some(area),
+        }))
+    }
+
+    /// Sub-satellite point at `time` relative to a storm centered at
+    /// (`longitude`, `latitude`) and moving towards `heading`
+    pub fn storm_relative_point(
+        &self,
+        time: f64,
+        longitude: f64,
+        latitude: f64,
+        heading: Option<f64>,
+    ) -> Result<StormRelativePoint, OrbitalError> {
+        let state = self.propagate(time)?;
+        let (s12, azi1, _, _): (f64, f64, f64, f64) =
+            self.geod.inverse(latitude, longitude, state.latitude, state.longitude);
+        let bearing = azi1.rem_euclid(360.0);
+        let relative_bearing = heading.map(|h| (bearing - h).rem_euclid(360.0));
+        Ok(StormRelativePoint {
+            time,
+            range: s12 / 1000.0,
+            bearing,
+            relative_bearing,
+            quadrant: relative_bearing.map(StormQuadrant::from_relative_bearing),
+            latitude: state.latitude,
+            longitude: state.longitude,
+        })
+    }
+
+    /// Sub-satellite track sampled every `step_sec` within `half_window_sec` of
+    /// `center_utc`, relative to a storm fixed at its position at `center_utc`
+    pub fn storm_relative_track(
+        &self,
+        center_utc: f64,
+        half_window_sec: f64,
+        step_sec: f64,
+        longitude: f64,
+        latitude: f64,
+        heading: Option<f64>,
+    ) -> Result<Vec<StormRelativePoint>, OrbitalError> {
+        let n_steps = (half_window_sec / step_sec).floor() as i64;
+        (-n_steps..=n_steps)
+            .map(|k| {
+                let time = center_utc + k as f64 * step_sec;
+                self.storm_relative_point(time, longitude, latitude, heading)
+            })
+            .collect()
+    }
+
+    // Distance in km from a point to the scan line at `time`, the segment
+    // reaching `swath_km` to either side of the sub-satellite point across
+    // the ground track
+    fn scan_line_distance(
+        &self,
+        time: f64,
+        latitude: f64,
+        longitude: f64,
+        swath_km: f64,
+    ) -> Result<f64, OrbitalError> {
+        let state = self.propagate(time)?;
+        let (lat, lon) = (state.latitude.to_radians(), state.longitude.to_radians());
+        let v = state.velocity;
+        let east = -lon.sin() * v[0] + lon.cos() * v[1];
+        let north = -lat.sin() * lon.cos() * v[0] - lat.sin() * lon.sin() * v[1] + lat.cos() * v[2];
+        let heading = east.atan2(north).to_degrees();
+        let (s12, azi1, _, _): (f64, f64, f64, f64) =
+            self.geod.inverse(state.latitude, state.longitude, latitude, longitude);
+        let angle = (azi1 - heading).to_radians();
+        let along = s12 / 1000.0 * angle.cos();
+        let cross = (s12 / 1000.0 * angle.sin()).abs();
+        Ok(if cross <= swath_km { along.abs() } else { along.hypot(cross - swath_km) })
     }
 
-    pub fn get_passes(&self, start_utc: f64, interval_sec: f64, longitude: f64, latitude: f64) -> Vec<SatPassEvent> {
-        let geod = Geodesic::wgs84();
-        let latitude_rad = latitude.to_radians();
-        let longitude_rad = longitude.to_radians();
-        let oe = ObserverElements {
-            elements: &self.elements,
-            constants: &self.constants,
-            observer: &PredictObserver {
-                name: "Observer".to_string(),
-                latitude: latitude_rad,
-                longitude: longitude_rad,
-                altitude: 0.,                 // meters
-                min_elevation: 0.0
-            },
+    /// Times the swath reaching `swath_km` to either side of the ground track
+    /// starts and stops covering part of the disk of `radius_km` around a
+    /// target fixed at its position at `cpa_time`, searched within
+    /// `half_window_sec` of `cpa_time`. None if the swath misses the disk.
+    pub fn footprint_interval(
+        &self,
+        cpa_time: f64,
+        half_window_sec: f64,
+        longitude: f64,
+        latitude: f64,
+        swath_km: f64,
+        radius_km: f64,
+    ) -> Result<Option<(f64, f64)>, OrbitalError> {
+        let covers = |time: f64| {
+            Ok::<_, OrbitalError>(self.scan_line_distance(time, latitude, longitude, swath_km)? <= radius_km)
         };
+        if !covers(cpa_time)? {
+            return Ok(None);
+        }
+        // The scan line moves away from the target on either side of CPA, so
+        // each edge is bracketed between CPA and the end of the window
+        let edge = |outer: f64| {
+            if covers(outer)? {
+                return Ok::<_, OrbitalError>(outer);
+            }
+            let (mut inside, mut outside) = (cpa_time, outer);
+            while (outside - inside).abs() > 0.1 {
+                let middle = (inside + outside) / 2.0;
+                if covers(middle)? {
+                    inside = middle;
+                } else {
+                    outside = middle;
+                }
+            }
+            Ok(inside)
+        };
+        Ok(Some((edge(cpa_time - half_window_sec)?, edge(cpa_time + half_window_sec)?)))
+    }
 
-        let passes = build_passes(&oe, start_utc, start_utc + interval_sec, false);
-        let mut pass_events: Vec<SatPassEvent> = Vec::with_capacity(passes.passes.len());
-
-        for pass in passes.passes.iter() {
-            // Filter out passes without AOS or LOS
-            if pass.aos.is_none() || pass.los.is_none() {
-                continue;
-            }
-            let aos = pass.aos.as_ref().expect("Missing AOS");
-            let los = pass.los.as_ref().expect("Missing LOS");
-
-            let (max_elev_deg, max_elev_time, orbit_at_cpa) = find_max_elevation(&oe, aos.time, los.time);
-            // let obs_at_cpa = predict_observe_orbit(&oe.observer, &orbit_at_cpa);
-
-            pass_events.push(SatPassEvent {
-                cpa_time: max_elev_time,
-                cpa_distance: geodesic_distance(
-                    &geod,
-                    latitude,
-                    longitude,
-                    orbit_at_cpa.latitude.to_degrees(),
-                    orbit_at_cpa.longitude.to_degrees(),
-                ),
-                elevation: max_elev_deg,
-            });
+    /// Closest approaches of the ground track to a moving target with CPA in
+    /// [`start_utc`, `start_utc + interval_sec`) while the satellite is above
+    /// the target horizon. `position_at` gives the target (latitude, longitude)
+    /// in degrees at a time, the search stops at the first time it gives None.
+    pub fn get_passes<F: Fn(f64) -> Option<(f64, f64)>>(
+        &self,
+        start_utc: f64,
+        interval_sec: f64,
+        position_at: F,
+    ) -> Result<Vec<SatPassEvent>, OrbitalError> {
+        if self.is_geostationary() {
+            // A geostationary satellite never rises or sets, see get_geo_scans
+            return Ok(Vec::new());
+        }
+        let states = coarse_times(start_utc, interval_sec)
+            .map(|time| Ok((time, self.propagate(time)?)))
+            .collect::<Result<Vec<(f64, SatState)>, OrbitalError>>()?;
+        cpa_states(
+            &states,
+            start_utc,
+            start_utc + interval_sec,
+            |time| self.propagate(time),
+            &position_at,
+        )?
+        .into_iter()
+        .filter_map(|(time, state)| {
+            self.pass_event(time, &state, &position_at, &|time| self.propagate(time)).transpose()
+        })
+        .collect()
+    }
+
+    // Describe the pass with closest approach at `cpa_time`, or None if the
+    // satellite is below the target horizon or the target position is unknown.
+    // The node and revolution are found on the states of `state_at`.
+    fn pass_event<F, S>(
+        &self,
+        cpa_time: f64,
+        state: &SatState,
+        position_at: &F,
+        state_at: &S,
+    ) -> Result<Option<SatPassEvent>, OrbitalError>
+    where
+        F: Fn(f64) -> Option<(f64, f64)>,
+        S: Fn(f64) -> Result<SatState, OrbitalError>,
+    {
+        let Some((latitude, longitude)) = position_at(cpa_time) else {
+            return Ok(None);
+        };
+        let target = geodetic_to_ecef(latitude.to_radians(), longitude.to_radians(), 0.0);
+        let elevation = 90.0 - zenith_angle(target, local_up(latitude, longitude), state.ecef);
+        if elevation < 0.0 {
+            return Ok(None);
+        }
+        let node = OrbitNode::from_velocity(state.velocity[2]);
+        let (crossing_time, crossing_lon) = find_equator_crossing(state_at, cpa_time, node)?;
+        let revolution = self.revolution_with(cpa_time, state_at)?;
+
+        Ok(Some(SatPassEvent {
+            cpa_time,
+            cpa_distance: geodesic_distance(
+                &self.geod,
+                latitude,
+                longitude,
+                state.latitude,
+                state.longitude,
+            ),
+            elevation,
+            node: Some(node),
+            orbit_number: Some(revolution.number),
+            orbit_start: Some(revolution.ascending_node),
+            orbit_period: Some(revolution.period),
+            local_solar_time: local_solar_time(cpa_time, longitude),
+            equator_crossing_time: local_solar_time(crossing_time, crossing_lon),
+            scan_start: None,
+            scan_area: None,
+        }))
+    }
+}
+
+// Find the equator crossing on the given node closest to `time` in the
+// states of `state_at`, returning the crossing time and longitude in degrees
+fn find_equator_crossing<S>(
+    state_at: &S,
+    time: f64,
+    node: OrbitNode,
+) -> Result<(f64, f64), OrbitalError>
+where
+    S: Fn(f64) -> Result<SatState, OrbitalError>,
+{
+    let step_sec = 60.0;
+    let latitude = state_at(time)?.latitude;
+    // Walk towards the equator along the current node
+    let direction = match (node, latitude >= 0.0) {
+        (OrbitNode::Ascending, true) | (OrbitNode::Descending, false) => -1.0,
+        _ => 1.0,
+    };
+    let sign = latitude.signum();
+    let mut lower_time = time;
+    let mut upper_time = time + direction * step_sec;
+    // A quarter of any LEO orbit is well under 60 steps
+    let mut crossed = false;
+    for _ in 0..60 {
+        if state_at(upper_time)?.latitude.signum() != sign {
+            crossed = true;
+            break;
+        }
+        lower_time = upper_time;
+        upper_time += direction * step_sec;
+    }
+    if !crossed {
+        return Err(OrbitalError::NonConvergence { time });
+    }
+    while (upper_time - lower_time).abs() > 1e-3 {
+        let mid_time = (upper_time + lower_time) / 2.0;
+        if state_at(mid_time)?.latitude.signum() == sign {
+            lower_time = mid_time;
+        } else {
+            upper_time = mid_time;
+        }
+    }
+    Ok((upper_time, state_at(upper_time)?.longitude))
+}
+
+// Coarse sample times covering the interval with one extra sample on each side
+fn coarse_times(start_utc: f64, interval_sec: f64) -> impl Iterator<Item = f64> {
+    let n_steps = (interval_sec / COARSE_STEP_SEC).ceil() as i64;
+    (-1..=n_steps + 1).map(move |k| start_utc + k as f64 * COARSE_STEP_SEC)
+}
+
+// Closest approaches to a moving target in [`start_utc`, `stop_utc`), bracketed
+// by the coarse `states` and refined with `state_at`. The samples end at the
+// first time the target position is unknown.
+fn cpa_states<S, F>(
+    states: &[(f64, SatState)],
+    start_utc: f64,
+    stop_utc: f64,
+    state_at: S,
+    position_at: &F,
+) -> Result<Vec<(f64, SatState)>, OrbitalError>
+where
+    S: Fn(f64) -> Result<SatState, OrbitalError>,
+    F: Fn(f64) -> Option<(f64, f64)>,
+{
+    let distance = |time: f64| {
+        let state = state_at(time)?;
+        let (latitude, longitude) = position_at(time).ok_or(OrbitalError::UnknownPosition { time })?;
+        Ok(great_circle_angle(latitude, longitude, state.latitude, state.longitude))
+    };
+    let samples = states
+        .iter()
+        .map_while(|(time, state)| {
+            let (latitude, longitude) = position_at(*time)?;
+            Some((*time, great_circle_angle(latitude, longitude, state.latitude, state.longitude)))
+        })
+        .collect::<Vec<(f64, f64)>>();
+
+    let mut cpas = Vec::new();
+    for window in samples.windows(3) {
+        let [(t0, d0), (_, d1), (t2, d2)] = [window[0], window[1], window[2]];
+        if !(d0 > d1 && d1 <= d2) {
+            continue;
+        }
+        let (cpa_time, _) = brent_minimize(distance, t0, t2, 1e-3)?;
+        if cpa_time < start_utc || cpa_time >= stop_utc {
+            continue;
+        }
+        cpas.push((cpa_time, state_at(cpa_time)?));
+    }
+    Ok(cpas)
+}
+
+struct EphemerisNode<'a> {
+    state: SatState,
+    orbital: &'a Orbital,
+    // This orbit at the previous and next grid times, where the neighbouring
+    // node was propagated with other elements
+    previous: Option<SatState>,
+    next: Option<SatState>,
+}
+
+// Cubic Hermite interpolation of position and velocity at fraction `s` of a
+// grid step of `h` seconds
+fn hermite(n0: &SatState, n1: &SatState, s: f64, h: f64) -> SatState {
+    let (s2, s3) = (s * s, s * s * s);
+    let (h00, h10, h01, h11) =
+        (2.0 * s3 - 3.0 * s2 + 1.0, s3 - 2.0 * s2 + s, -2.0 * s3 + 3.0 * s2, s3 - s2);
+    let (d00, d10, d01, d11) =
+        (6.0 * s2 - 6.0 * s, 3.0 * s2 - 4.0 * s + 1.0, -6.0 * s2 + 6.0 * s, 3.0 * s2 - 2.0 * s);
+    let mut ecef = [0.0; 3];
+    let mut velocity = [0.0; 3];
+    for i in 0..3 {
+        ecef[i] = h00 * n0.ecef[i]
+            + h10 * h * n0.velocity[i]
+            + h01 * n1.ecef[i]
+            + h11 * h * n1.velocity[i];
+        velocity[i] = (d00 * n0.ecef[i] + d01 * n1.ecef[i]) / h
+            + d10 * n0.velocity[i]
+            + d11 * n1.velocity[i];
+    }
+    SatState::from_ecef(ecef, velocity)
+}
+
+/// Satellite states propagated once on a fixed time grid and interpolated in
+/// between, so passes over any number of targets share one propagation.
+/// Grid times that could not be propagated keep their error, which is
+/// returned by any query that needs them.
+pub struct Ephemeris<'a> {
+    start_utc: f64,
+    step_sec: f64,
+    nodes: Vec<Result<EphemerisNode<'a>, OrbitalError>>,
+}
+
+impl<'a> Ephemeris<'a> {
+    /// Propagate one element set every `step_sec` over [`start_utc`, `stop_utc`]
+    pub fn new(orbital: &'a Orbital, start_utc: f64, stop_utc: f64, step_sec: f64) -> Self {
+        Self::build(start_utc, stop_utc, step_sec, |_| std::iter::once(orbital))
+    }
+
+    /// Propagate every `step_sec` over [`start_utc`, `stop_utc`] with the element
+    /// set nearest in epoch to each grid time, trying up to `max_candidates`
+    /// sets in order of epoch distance when propagation fails.
+    /// `orbitals[i]` is the orbit of `tle_manager.tles[i]`, if usable.
+    pub fn from_tles(
+        tle_manager: &tle::TLEManager,
+        orbitals: &'a [Option<Orbital>],
+        start_utc: f64,
+        stop_utc: f64,
+        step_sec: f64,
+        max_candidates: usize,
+    ) -> Self {
+        Self::build(start_utc, stop_utc, step_sec, |time| {
+            tle_manager
+                .nearest_tle_indices(time, max_candidates)
+                .into_iter()
+                .filter_map(|index| orbitals[index].as_ref())
+        })
+    }
+
+    fn build<C, I>(start_utc: f64, stop_utc: f64, step_sec: f64, candidates: C) -> Self
+    where
+        C: Fn(f64) -> I,
+        I: Iterator<Item = &'a Orbital>,
+    {
+        let n_nodes = ((stop_utc - start_utc) / step_sec).ceil().max(1.0) as usize + 1;
+        let grid_time = |k: usize| start_utc + k as f64 * step_sec;
+        let mut nodes: Vec<_> = (0..n_nodes)
+            .map(|k| {
+                let time = grid_time(k);
+                let mut propagation_error = None;
+                for orbital in candidates(time) {
+                    match orbital.propagate(time) {
+                        Ok(state) => {
+                            return Ok(EphemerisNode { state, orbital, previous: None, next: None });
+                        }
+                        Err(err) if err.is_propagation_failure() => propagation_error = Some(err),
+                        Err(err) => return Err(err),
+                    }
+                }
+                Err(propagation_error.unwrap_or(OrbitalError::NoElements { time }))
+            })
+            .collect();
+        // Where the elements change, each orbit is also propagated to the far
+        // end of the step so that no interpolation mixes two element sets
+        for k in 0..n_nodes - 1 {
+            let (head, tail) = nodes.split_at_mut(k + 1);
+            if let (Ok(n0), Ok(n1)) = (&mut head[k], &mut tail[0])
+                && !std::ptr::eq(n0.orbital, n1.orbital)
+            {
+                n0.next = n0.orbital.propagate(grid_time(k + 1)).ok();
+                n1.previous = n1.orbital.propagate(grid_time(k)).ok();
+            }
+        }
+        Ephemeris { start_utc, step_sec, nodes }
+    }
+
+    /// Time span covered by the grid
+    pub fn time_range(&self) -> (f64, f64) {
+        let stop_utc = self.start_utc + (self.nodes.len() - 1) as f64 * self.step_sec;
+        (self.start_utc, stop_utc)
+    }
+
+    fn node(&self, k: usize) -> Result<&EphemerisNode<'a>, OrbitalError> {
+        self.nodes[k].as_ref().map_err(OrbitalError::clone)
+    }
+
+    /// Element set propagated at the grid time nearest to `time`
+    pub fn orbital_at(&self, time: f64) -> Result<&'a Orbital, OrbitalError> {
+        let k = ((time - self.start_utc) / self.step_sec).round();
+        Ok(self.node(k.clamp(0.0, (self.nodes.len() - 1) as f64) as usize)?.orbital)
+    }
+
+    /// State at `time` from cubic Hermite interpolation of position and
+    /// velocity between the enclosing grid times. Grid times return the
+    /// propagated state as is. Where the two grid times use different
+    /// element sets, the step is split at its middle and each half is
+    /// interpolated on the orbit of its nearer grid time, as `orbital_at`.
+    pub fn state(&self, time: f64) -> Result<SatState, OrbitalError> {
+        let (start_utc, stop_utc) = self.time_range();
+        if !(start_utc..=stop_utc).contains(&time) {
+            return Err(OrbitalError::OutsideEphemeris { time });
+        }
+        let offset = (time - start_utc) / self.step_sec;
+        let k = (offset.floor() as usize).min(self.nodes.len() - 2);
+        let s = offset - k as f64;
+        let n0 = self.node(k)?;
+        if s == 0.0 {
+            return Ok(n0.state.clone());
+        }
+        let n1 = self.node(k + 1)?;
+        let (s0, s1) = if std::ptr::eq(n0.orbital, n1.orbital) {
+            (&n0.state, &n1.state)
+        } else if s < 0.5 {
+            (&n0.state, n0.next.as_ref().unwrap_or(&n1.state))
+        } else {
+            (n1.previous.as_ref().unwrap_or(&n0.state), &n1.state)
+        };
+        Ok(hermite(s0, s1, s, self.step_sec))
+    }
+
+    /// Closest approaches to a moving target, as `Orbital::get_passes` but
+    /// from the interpolated states. The grid must cover the interval with a
+    /// margin of two minutes on each side, and of an orbit before it to find
+    /// the ascending node.
+    pub fn get_passes<F: Fn(f64) -> Option<(f64, f64)>>(
+        &self,
+        start_utc: f64,
+        interval_sec: f64,
+        position_at: F,
+    ) -> Result<Vec<SatPassEvent>, OrbitalError> {
+        if self.orbital_at(start_utc)?.is_geostationary() {
+            return Ok(Vec::new());
+        }
+        let states = coarse_times(start_utc, interval_sec)
+            .map(|time| Ok((time, self.state(time)?)))
+            .collect::<Result<Vec<(f64, SatState)>, OrbitalError>>()?;
+        let state_at = |time| self.state(time);
+        cpa_states(&states, start_utc, start_utc + interval_sec, state_at, &position_at)?
+            .into_iter()
+            .filter_map(|(time, state)| {
+                self.orbital_at(time)
+                    .and_then(|orbital| orbital.pass_event(time, &state, &position_at, &state_at))
+                    .transpose()
+            })
+            .collect()
+    }
+}
+
+/// Events shared by the tests
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::TCSatPassEvent;
+
+    /// Aqua pass over a storm at `cpa_time`, without node, orbit, scan or
+    /// footprint; override fields with struct update syntax
+    pub fn tc_event(cpa_time: f64) -> TCSatPassEvent {
+        TCSatPassEvent {
+            cpa_time,
+            cpa_distance: 100.0,
+            sat_zenith: 10.0,
+            intensity: Some(100.0),
+            storm_latitude: 10.0,
+            storm_longitude: 130.0,
+            norad_id: 27424,
+            tle_epoch: 0.0,
+            node: None,
+            orbit_number: None,
+            orbit_start: None,
+            orbit_period: None,
+            local_solar_time: 0.0,
+            equator_crossing_time: 0.0,
+            scan_start: None,
+            scan_area: None,
+            footprint_start: None,
+            footprint_end: None,
+            cpa_relative: None,
+            track: Vec::new(),
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_solar_time() {
+        // 2023-02-14 12:00 UTC
+        assert_eq!(local_solar_time(1676376000.0, 0.0), 12.0);
+        assert_eq!(local_solar_time(1676376000.0, 150.0), 22.0);
+        assert_eq!(local_solar_time(1676376000.0, 270.0), 6.0);
+    }
+
+    #[test]
+    fn test_storm_quadrant() {
+        assert_eq!(StormQuadrant::from_relative_bearing(45.0), StormQuadrant::FrontRight);
+        assert_eq!(StormQuadrant::from_relative_bearing(135.0), StormQuadrant::RearRight);
+        assert_eq!(StormQuadrant::from_relative_bearing(225.0), StormQuadrant::RearLeft);
+        assert_eq!(StormQuadrant::from_relative_bearing(-10.0), StormQuadrant::FrontLeft);
+    }
+
+    fn tc_event(cpa_time: f64, node: OrbitNode, orbit_number: u64) -> TCSatPassEvent {
+        TCSatPassEvent {
+            node: Some(node),
+            orbit_number: Some(orbit_number),
+            orbit_start: Some(0.0),
+            orbit_period: Some(6000.0),
+            ..fixtures::tc_event(cpa_time)
+        }
+    }
+
+    #[test]
+    fn test_dedup_passes() {
+        let mut events = vec![
+            tc_event(6000.0, OrbitNode::Ascending, 2),
+            tc_event(100.0, OrbitNode::Ascending, 1),
+            tc_event(6000.5, OrbitNode::Ascending, 2),
+            tc_event(100.2, OrbitNode::Descending, 1),
+        ];
+        dedup_passes(&mut events);
+        let times: Vec<f64> = events.iter().map(|e| e.cpa_time).collect();
+        assert_eq!(times, vec![100.0, 100.2, 6000.0]);
+
+        // Three overlapping windows find one pass with elements of different
+        // epochs; the first and last finds are not neighbours once sorted
+        let with_epoch = |cpa_time: f64, tle_epoch: f64| TCSatPassEvent {
+            tle_epoch,
+            ..tc_event(cpa_time, OrbitNode::Descending, 5)
+        };
+        let mut events = vec![
+            with_epoch(30000.0, -86400.0),
+            with_epoch(30001.0, 0.0),
+            with_epoch(29999.5, 30000.0),
+            tc_event(30000.5, OrbitNode::Ascending, 5),
+        ];
+        dedup_passes(&mut events);
+        let kept: Vec<(f64, f64)> = events.iter().map(|e| (e.cpa_time, e.tle_epoch)).collect();
+        assert_eq!(kept, vec![(29999.5, 30000.0), (30000.5, 0.0)]);
+
+        // Element sets of different epochs number the same pass one
+        // revolution apart
+        let mut events = vec![
+            TCSatPassEvent { tle_epoch: 0.0, ..tc_event(50000.0, OrbitNode::Ascending, 8) },
+            TCSatPassEvent { tle_epoch: 50000.0, ..tc_event(50002.0, OrbitNode::Ascending, 9) },
+            tc_event(56000.0, OrbitNode::Ascending, 9),
+        ];
+        dedup_passes(&mut events);
+        let kept: Vec<(f64, Option<u64>)> = events.iter().map(|e| (e.cpa_time, e.orbit_number)).collect();
+        assert_eq!(kept, vec![(50002.0, Some(9)), (56000.0, Some(9))]);
+    }
+
+    #[test]
+    fn test_brent_minimize() {
+        let (x, fx) = brent_minimize(|x| Ok((x - 1.5).powi(2) + 2.0), 0.0, 4.0, 1e-8).unwrap();
+        assert!((x - 1.5).abs() < 1e-6);
+        assert!((fx - 2.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_ephemeris_interpolation() {
+        let tle = tle::fixtures::aqua();
+        let orbital = Orbital::new(&tle).unwrap();
+        let start_utc = 1383134400.0;
+        let ephemeris = Ephemeris::new(&orbital, start_utc, start_utc + 7200.0, 60.0);
+        for k in 0..240 {
+            let time = start_utc + k as f64 * 29.7;
+            let expected = orbital.propagate(time).unwrap();
+            let state = ephemeris.state(time).unwrap();
+            for i in 0..3 {
+                assert!((state.ecef[i] - expected.ecef[i]).abs() < 0.01);
+                assert!((state.velocity[i] - expected.velocity[i]).abs() < 1e-4);
+            }
+        }
+        assert!(ephemeris.state(start_utc - 1.0).is_err());
+    }
+
+    #[test]
+    fn test_ephemeris_tle_switch() {
+        // The same elements half a day apart, far apart on the orbit at any time
+        let (line1, line2) = tle::fixtures::aqua_lines();
+        let later = "1 27424U 02022A   13304.00000000  .00000100  00000-0  32000-4 0  9996";
+        let tles = [line1, later].iter().map(|line1| tle::TLE::new(line1, line2).unwrap()).collect();
+        let tle_manager = tle::TLEManager { tles };
+        let orbitals: Vec<Option<Orbital>> =
+            tle_manager.tles.iter().map(|tle| Orbital::new(tle).ok()).collect();
+        // The nearest element set changes at 18 UTC, halfway through a grid step
+        let switch_utc = 1383156000.0;
+        let (start_utc, stop_utc) = (switch_utc - 1830.0, switch_utc + 1830.0);
+        let ephemeris = Ephemeris::from_tles(&tle_manager, &orbitals, start_utc, stop_utc, 60.0, 1);
+        for time in [switch_utc - 29.0, switch_utc - 1.0, switch_utc + 1.0, switch_utc + 29.0] {
+            let orbital = ephemeris.orbital_at(time).unwrap();
+            assert_eq!(orbital.epoch() > switch_utc, time > switch_utc);
+            let expected = orbital.propagate(time).unwrap();
+            let state = ephemeris.state(time).unwrap();
+            for i in 0..3 {
+                assert!((state.ecef[i] - expected.ecef[i]).abs() < 0.01, "{} {:?}", time, state.ecef);
+            }
+        }
+    }
+
+    #[test]
+    fn test_revolution_at() {
+        // Revolution 60000 at epoch, 208.7° past the ascending node
+        let tle = tle::fixtures::aqua();
+        let orbital = Orbital::new(&tle).unwrap();
+        let revolution = orbital.revolution_at(orbital.epoch()).unwrap();
+        assert_eq!(revolution.number, 60000);
+        let node = orbital.propagate(revolution.ascending_node).unwrap();
+        assert!(node.latitude.abs() < 1e-3 && node.velocity[2] > 0.0);
+        let after_node = orbital.revolution_at(revolution.ascending_node + 5.0).unwrap();
+        assert_eq!(after_node.number, 60000);
+        assert!((after_node.ascending_node - revolution.ascending_node).abs() < 0.1);
+        assert_eq!(orbital.revolution_at(revolution.ascending_node - 5.0).unwrap().number, 59999);
+    }
+
+    #[test]
+    fn test_orbit_node_from_velocity() {
+        // Both equator crossings of one revolution, and the motion just
+        // after each agrees with the node
+        let orbital = Orbital::new(&tle::fixtures::aqua()).unwrap();
+        let revolution = orbital.revolution_at(orbital.epoch()).unwrap();
+        for (offset, expected) in [(0.0, OrbitNode::Ascending), (0.5, OrbitNode::Descending)] {
+            let time = revolution.ascending_node + offset * revolution.period;
+            let state = orbital.propagate(time).unwrap();
+            assert!(state.latitude.abs() < 1.0, "{}", state.latitude);
+            assert_eq!(OrbitNode::from_velocity(state.velocity[2]), expected);
+            let later = orbital.propagate(time + 60.0).unwrap();
+            assert_eq!(later.latitude > state.latitude, expected == OrbitNode::Ascending);
+        }
+    }
+
+    #[test]
+    fn test_geo_scan_line_time() {
+        let tle = tle::TLE::new(
+            "1 40267U 02022A   13303.50000000  .00000100  00000-0  32000-4 0  9990",
+            "2 40267   0.0300 100.0000 0001000 200.0000   0.0000  1.00270000 10009",
+        )
+        .unwrap();
+        let orbital = Orbital::new(&tle).unwrap();
+        let mut schedule = GeoScanSchedule {
+            interval_sec: 600.0,
+            duration_sec: 600.0,
+            south_to_north: false,
+            sector: None,
+        };
+        assert_eq!(schedule.line_fraction(10.0, -10.0, 10.0), 0.0);
+        assert_eq!(schedule.line_fraction(5.0, -10.0, 10.0), 0.25);
+        assert_eq!(schedule.line_fraction(-20.0, -10.0, 10.0), 1.0);
+
+        // A target below the satellite is scanned halfway down the disk
+        let start_utc = 1383177600.0;
+        let below = orbital.propagate(start_utc).unwrap();
+        let target = |latitude: f64| move |_: f64| Some((latitude, below.longitude));
+        let scans = orbital.get_geo_scans(start_utc, 1200.0, target(below.latitude), &schedule, 80.0).unwrap();
+        let line_offsets: Vec<f64> = scans.iter().map(|scan| scan.cpa_time - scan.scan_start.unwrap()).collect();
+        assert_eq!(scans.len(), 2);
+        assert!(line_offsets.iter().all(|offset| (offset - 300.0).abs() < 1.0), "{:?}", line_offsets);
+        assert!(scans.iter().all(|scan| scan.node.is_none() && scan.orbit_number.is_none()));
+        assert!(scans.iter().all(|scan| scan.orbit_start.is_none() && scan.orbit_period.is_none()));
+
+        // A quarter of the way up a 20 degree sector scanned every 150 s
+        schedule.south_to_north = true;
+        schedule.sector = Some(GeoSector {
+            interval_sec: 150.0,
+            duration_sec: 120.0,
+            south: 10.0,
+            north: 30.0,
+            west: below.longitude - 10.0,
+            east: below.longitude + 10.0,
+        });
+        let scans = orbital.get_geo_scans(start_utc, 600.0, target(15.0), &schedule, 80.0).unwrap();
+        let sector_offsets: Vec<f64> = scans
+            .iter()
+            .filter(|scan| scan.scan_area == Some(ScanArea::Sector))
+            .map(|scan| scan.cpa_time - scan.scan_start.unwrap())
+            .collect();
+        assert_eq!(sector_offsets, vec![30.0; 4]);
+        assert_eq!(scans.len(), 5);
+        assert!(scans.windows(2).all(|pair| pair[0].cpa_time <= pair[1].cpa_time));
+        // Outside the sector only the full disk sees the target
+        let scans = orbital.get_geo_scans(start_utc, 600.0, target(-15.0), &schedule, 80.0).unwrap();
+        assert_eq!(scans.iter().map(|scan| scan.scan_area).collect::<Vec<_>>(), vec![Some(ScanArea::FullDisk)]);
+    }
 
-        pass_events
+    #[test]
+    fn test_footprint_interval() {
+        let tle = tle::fixtures::aqua();
+        let orbital = Orbital::new(&tle).unwrap();
+        let cpa_time = orbital.epoch() + 600.0;
+        let below = orbital.propagate(cpa_time).unwrap();
+        // A target under the ground track is covered while the scan line is
+        // within the radius, about 500 km / 6.8 km/s on either side
+        let (start, end) = orbital
+            .footprint_interval(cpa_time, 600.0, below.longitude, below.latitude, 1000.0, 500.0)
+            .unwrap()
+            .unwrap();
+        assert!((cpa_time - start - 73.0).abs() < 3.0 && (end - cpa_time - 73.0).abs() < 3.0);
+        // Half an orbit later the swath is on the far side of the Earth
+        let missed =
+            orbital.footprint_interval(cpa_time + 3000.0, 600.0, below.longitude, below.latitude, 1000.0, 500.0);
+        assert!(missed.unwrap().is_none());
     }
 }
@@ -0,0 +1,453 @@
+//! Writing pass events as text lines, CSV rows or JSON
+
+use std::io::{self, Write};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::orbital::{ScanArea, TCSatPassEvent};
+use crate::search::SearchParams;
+
+/// Layout of written events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned lines for reading
+    Text,
+    /// One row per event after a header line
+    Csv,
+    /// One document with the run metadata and an array of events
+    Json,
+    /// The run metadata, then one event object per line
+    Jsonl,
+}
+
+pub fn dt_from_unix_seconds(t_utc: f64) -> DateTime<Utc> {
+    let micros = (t_utc * 1_000_000.0).round() as i64;
+    DateTime::<Utc>::from_timestamp_micros(micros).expect("timestamp out of range")
+}
+
+pub fn intensity_fmt(intensity: Option<f64>) -> String {
+    intensity.map_or_else(|| "  -".to_string(), |kt| format!("{:3.0}", kt))
+}
+
+fn hours_fmt(hours: f64) -> String {
+    let minutes = (hours * 60.0).round() as i64 % 1440;
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+pub(crate) fn iso_fmt(t_utc: f64) -> String {
+    dt_from_unix_seconds(t_utc).format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// Description of the run written ahead of JSON events
+#[derive(Debug, Clone, Serialize)]
+pub struct RunMetadata {
+    pub software: &'static str,
+    pub version: &'static str,
+    /// Time the output was produced, ISO 8601
+    pub generated: String,
+    /// Subcommand or mode, e.g. passes or sites
+    pub command: String,
+    /// Input file paths
+    pub inputs: Vec<String>,
+    /// Search range, ISO 8601, for searches not bounded by a track
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    pub params: SearchParams,
+}
+
+impl RunMetadata {
+    pub fn new(command: &str, inputs: Vec<String>, params: SearchParams) -> Self {
+        RunMetadata {
+            software: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            generated: Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            command: command.to_string(),
+            inputs,
+            start: None,
+            end: None,
+            params,
+        }
+    }
+
+    /// Record the time range of a search over fixed targets
+    pub fn with_range(mut self, start_utc: f64, end_utc: f64) -> Self {
+        self.start = Some(iso_fmt(start_utc));
+        self.end = Some(iso_fmt(end_utc));
+        self
+    }
+}
+
+// Event as written to JSON, with the names and readable times the
+// event itself does not carry
+#[derive(Serialize)]
+struct JsonEvent<'a> {
+    time: String,
+    storm_id: &'a str,
+    satellite: &'a str,
+    tle_epoch_time: String,
+    granules: Vec<String>,
+    #[serde(flatten)]
+    event: &'a TCSatPassEvent,
+}
+
+#[derive(Serialize)]
+struct JsonMetadata<'a> {
+    metadata: &'a Option<RunMetadata>,
+}
+
+// Quote a CSV field if it contains a separator, quote or line break
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Satellite and target the events of one write belong to
+pub struct EventSource<'a> {
+    /// Storm ID or site name
+    pub target: &'a str,
+    pub satellite: &'a str,
+}
+
+/// Options of an `EventWriter`
+#[derive(Debug, Clone)]
+pub struct WriterOptions {
+    pub format: OutputFormat,
+    /// Follow each text line with the storm-relative sub-satellite track
+    pub print_track: bool,
+    /// Start each text line with the target name
+    pub label_lines: bool,
+    /// Run description for JSON formats
+    pub metadata: Option<RunMetadata>,
+}
+
+/// Writes the events of one or more searches to a single output
+pub struct EventWriter {
+    out: Box<dyn Write>,
+    options: WriterOptions,
+    header_written: bool,
+    events_written: usize,
+}
+
+impl EventWriter {
+    pub fn new(out: Box<dyn Write>, options: WriterOptions) -> Self {
+        EventWriter {
+            out,
+            options,
+            header_written: false,
+            events_written: 0,
+        }
+    }
+
+    /// Write `events` found for `source`. `granules` names the granules
+    /// observing an event.
+    pub fn write_events<G: Fn(&TCSatPassEvent) -> Vec<String>>(
+        &mut self,
+        events: &[TCSatPassEvent],
+        source: &EventSource,
+        granules: G,
+    ) -> io::Result<()> {
+        match self.options.format {
+            OutputFormat::Text => self.write_text(events, source, granules),
+            OutputFormat::Csv => self.write_csv(events, source, granules),
+            OutputFormat::Json | OutputFormat::Jsonl => self.write_json(events, source, granules),
+        }
+    }
+
+    /// Complete and flush the output. Headers and metadata are written even
+    /// if no event was.
+    pub fn finish(mut self) -> io::Result<()> {
+        let no_source = EventSource { target: "", satellite: "" };
+        match self.options.format {
+            OutputFormat::Text => {}
+            OutputFormat::Csv => self.write_csv(&[], &no_source, |_| Vec::new())?,
+            OutputFormat::Jsonl => self.write_json(&[], &no_source, |_| Vec::new())?,
+            OutputFormat::Json => {
+                self.write_json(&[], &no_source, |_| Vec::new())?;
+                writeln!(self.out, "\n]}}")?;
+            }
+        }
+        self.out.flush()
+    }
+
+    fn write_json<G: Fn(&TCSatPassEvent) -> Vec<String>>(
+        &mut self,
+        events: &[TCSatPassEvent],
+        source: &EventSource,
+        granules: G,
+    ) -> io::Result<()> {
+        // A JSON document is streamed as the metadata object with an events
+        // array appended, one event per line
+        let is_lines = self.options.format == OutputFormat::Jsonl;
+        if !self.header_written {
+            if is_lines {
+                let metadata = JsonMetadata { metadata: &self.options.metadata };
+                serde_json::to_writer(&mut self.out, &metadata)?;
+                writeln!(self.out)?;
+            } else {
+                write!(self.out, "{{\"metadata\": ")?;
+                serde_json::to_writer(&mut self.out, &self.options.metadata)?;
+                write!(self.out, ",\n\"events\": [")?;
+            }
+            self.header_written = true;
+        }
+        for event in events.iter() {
+            let json_event = JsonEvent {
+                time: iso_fmt(event.cpa_time),
+                storm_id: source.target,
+                satellite: source.satellite,
+                tle_epoch_time: iso_fmt(event.tle_epoch),
+                granules: granules(event),
+                event,
+            };
+            if !is_lines {
+                writeln!(self.out, "{}", if self.events_written == 0 { "" } else { "," })?;
+            }
+            serde_json::to_writer(&mut self.out, &json_event)?;
+            if is_lines {
+                writeln!(self.out)?;
+            }
+            self.events_written += 1;
+        }
+        Ok(())
+    }
+
+    fn write_text<G: Fn(&TCSatPassEvent) -> Vec<String>>(
+        &mut self,
+        events: &[TCSatPassEvent],
+        source: &EventSource,
+        granules: G,
+    ) -> io::Result<()> {
+        let prefix = if self.options.label_lines {
+            format!("{}  ", source.target)
+        } else {
+            String::new()
+        };
+        for event in events.iter() {
+            let dt_cpa = dt_from_unix_seconds(event.cpa_time);
+            let granule_names = granules(event).join(" ");
+            if let Some(scan_start) = event.scan_start {
+                writeln!(self.out, "{}{} - Distance: {:4.0} km  Zenith: {:4.1}° Intensity: {} kt  LST: {}  Scan start: {}{}{}{}",
+                    prefix,
+                    dt_cpa.format("%Y-%m-%d %H:%M:%S"),
+                    event.cpa_distance,
+                    event.sat_zenith,
+                    intensity_fmt(event.intensity),
+                    hours_fmt(event.local_solar_time),
+                    dt_from_unix_seconds(scan_start).format("%Y-%m-%d %H:%M:%S"),
+                    if event.scan_area == Some(ScanArea::Sector) { " (target area)" } else { "" },
+                    if granule_names.is_empty() { "" } else { "  " },
+                    granule_names)?;
+                continue;
+            }
+            let quadrant = event
+                .cpa_relative
+                .as_ref()
+                .and_then(|point| point.quadrant)
+                .map_or_else(|| "-".to_string(), |q| q.to_string());
+            writeln!(self.out, "{}{} - Distance: {:4.0} km  Zenith: {:4.1}° Intensity: {} kt  Node: {} Orbit: {:>6}  LST: {}  ECT: {}  Quadrant: {:<11}  {}",
+                prefix,
+                dt_cpa.format("%Y-%m-%d %H:%M:%S"),
+                event.cpa_distance,
+                event.sat_zenith,
+                intensity_fmt(event.intensity),
+                event.node.map_or_else(|| "-".to_string(), |node| node.to_string()),
+                event.orbit_number.map_or_else(|| "-".to_string(), |orbit| orbit.to_string()),
+                hours_fmt(event.local_solar_time),
+                hours_fmt(event.equator_crossing_time),
+                quadrant,
+                if granule_names.is_empty() { " " } else { &granule_names })?;
+            if self.options.print_track {
+                for point in event.track.iter() {
+                    let relative = point
+                        .relative_bearing
+                        .map_or_else(|| "   -".to_string(), |b| format!("{:3.0}°", b));
+                    writeln!(self.out, "    {:+5.0} s  Range: {:4.0} km  Bearing: {:3.0}°  Relative: {}  {}",
+                        point.time - event.cpa_time,
+                        point.range,
+                        point.bearing,
+                        relative,
+                        point.quadrant.map_or_else(|| "-".to_string(), |q| q.to_string()))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_csv<G: Fn(&TCSatPassEvent) -> Vec<String>>(
+        &mut self,
+        events: &[TCSatPassEvent],
+        source: &EventSource,
+        granules: G,
+    ) -> io::Result<()> {
+        if !self.header_written {
+            writeln!(
+                self.out,
+                "time,storm_id,satellite,distance_km,zenith_deg,intensity_kt,storm_lat,storm_lon,granule,orbit,\
+                 node,local_solar_time,equator_crossing_time,scan_start,scan_area"
+            )?;
+            self.header_written = true;
+        }
+        // Columns left empty where the event has no value, as node and orbit
+        // for geostationary scans and the scan for passes
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        for event in events.iter() {
+            writeln!(
+                self.out,
+                "{},{},{},{:.1},{:.2},{},{:.3},{:.3},{},{},{},{},{},{},{}",
+                iso_fmt(event.cpa_time),
+                csv_field(source.target),
+                csv_field(source.satellite),
+                event.cpa_distance,
+                event.sat_zenith,
+                optional(event.intensity.map(|kt| format!("{:.0}", kt))),
+                event.storm_latitude,
+                event.storm_longitude,
+                csv_field(&granules(event).join(" ")),
+                optional(event.orbit_number.map(|orbit| orbit.to_string())),
+                optional(event.node.map(|node| node.to_string())),
+                hours_fmt(event.local_solar_time),
+                hours_fmt(event.equator_crossing_time),
+                optional(event.scan_start.map(iso_fmt)),
+                optional(event.scan_area.map(|area| area.to_string())),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orbital::{fixtures, OrbitNode};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Output kept readable after the writer owning it is finished
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Events written one search at a time, as for several storms
+    fn written(format: OutputFormat, n_events: usize) -> String {
+        let buffer = SharedBuffer::default();
+        let options = WriterOptions {
+            format,
+            print_track: false,
+            label_lines: false,
+            metadata: Some(RunMetadata::new("passes", vec!["aqua.txt".to_string()], SearchParams::default())),
+        };
+        let mut writer = EventWriter::new(Box::new(buffer.clone()), options);
+        for k in 0..n_events {
+            let event = TCSatPassEvent {
+                cpa_distance: 51.0,
+                sat_zenith: 4.6,
+                intensity: Some(110.0),
+                storm_latitude: 10.3,
+                storm_longitude: 147.2,
+                tle_epoch: 1383652800.0,
+                orbit_period: Some(5929.6),
+                local_solar_time: 1.9,
+                equator_crossing_time: 1.7,
+                ..fixtures::tc_event(1383667423.0 + k as f64 * 43200.0)
+            };
+            let storm_id = format!("WP3{}2013", k);
+            let source = EventSource { target: &storm_id, satellite: "aqua" };
+            writer.write_events(&[event], &source, |_| vec!["MYD021KM.A2013309.1600".to_string()]).unwrap();
+        }
+        writer.finish().unwrap();
+        let bytes = buffer.0.borrow().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_json_output() {
+        for n_events in 0..=2 {
+            let document: serde_json::Value = serde_json::from_str(&written(OutputFormat::Json, n_events)).unwrap();
+            assert_eq!(document["metadata"]["command"], "passes");
+            let events = document["events"].as_array().unwrap();
+            assert_eq!(events.len(), n_events);
+            for (k, event) in events.iter().enumerate() {
+                assert_eq!(event["storm_id"], format!("WP3{}2013", k));
+                assert_eq!(event["granules"][0], "MYD021KM.A2013309.1600");
+                assert_eq!(event["node"], serde_json::Value::Null);
+            }
+        }
+    }
+
+    #[test]
+    fn test_jsonl_output() {
+        for n_events in 0..=2 {
+            let output = written(OutputFormat::Jsonl, n_events);
+            let lines: Vec<serde_json::Value> =
+                output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+            assert_eq!(lines.len(), n_events + 1);
+            assert_eq!(lines[0]["metadata"]["inputs"][0], "aqua.txt");
+            assert!(lines[0].get("cpa_time").is_none());
+            for (k, event) in lines[1..].iter().enumerate() {
+                assert_eq!(event["storm_id"], format!("WP3{}2013", k));
+                assert_eq!(event["time"], if k == 0 { "2013-11-05T16:03:43Z" } else { "2013-11-06T04:03:43Z" });
+            }
+        }
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let output = written(OutputFormat::Csv, 2);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "time,storm_id,satellite,distance_km,zenith_deg,intensity_kt,storm_lat,storm_lon,granule,orbit,\
+                 node,local_solar_time,equator_crossing_time,scan_start,scan_area",
+                "2013-11-05T16:03:43Z,WP302013,aqua,51.0,4.60,110,10.300,147.200,MYD021KM.A2013309.1600,,\
+                 ,01:54,01:42,,",
+                "2013-11-06T04:03:43Z,WP312013,aqua,51.0,4.60,110,10.300,147.200,MYD021KM.A2013309.1600,,\
+                 ,01:54,01:42,,",
+            ]
+        );
+
+        let buffer = SharedBuffer::default();
+        let options = WriterOptions { format: OutputFormat::Csv, print_track: false, label_lines: false, metadata: None };
+        let mut writer = EventWriter::new(Box::new(buffer.clone()), options);
+        let pass = TCSatPassEvent {
+            node: Some(OrbitNode::Descending),
+            orbit_number: Some(60090),
+            ..fixtures::tc_event(1383667423.0)
+        };
+        let scan = TCSatPassEvent {
+            scan_start: Some(1383667200.0),
+            scan_area: Some(ScanArea::Sector),
+            ..fixtures::tc_event(1383667230.0)
+        };
+        let source = EventSource { target: "WP312013", satellite: "sat" };
+        writer.write_events(&[pass, scan], &source, |_| Vec::new()).unwrap();
+        writer.finish().unwrap();
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let columns: Vec<Vec<&str>> = output.lines().skip(1).map(|line| line.split(',').skip(9).collect()).collect();
+        assert_eq!(columns[0], ["60090", "descending", "00:00", "00:00", "", ""]);
+        assert_eq!(columns[1], ["", "", "00:00", "00:00", "2013-11-05T16:00:00Z", "sector"]);
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("WP312013"), "WP312013");
+        assert_eq!(csv_field("Mauna Kea, HI"), "\"Mauna Kea, HI\"");
+        assert_eq!(csv_field("6\" gauge"), "\"6\"\" gauge\"");
+        assert_eq!(csv_field("a\r\nb"), "\"a\r\nb\"");
+    }
+}
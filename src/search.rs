@@ -0,0 +1,511 @@
+//! Overpass search of one satellite along a storm or other moving target
+
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::orbital::{
+    dedup_passes, Ephemeris, GeoScanSchedule, Orbital, OrbitalError, OrbitNode, SatPassEvent,
+    TCSatPassEvent,
+};
+use crate::tle::TLEManager;
+use crate::track::Track;
+
+// Span and sampling of the storm-relative sub-satellite track around CPA
+const TRACK_HALF_WINDOW_SEC: f64 = 600.0;
+const TRACK_STEP_SEC: f64 = 30.0;
+// The TLE revolution field counts revolutions modulo this
+const REVOLUTION_FIELD_WRAP: u64 = 100_000;
+// TLEs tried per track point, nearest epoch first, when propagation fails
+const MAX_TLE_CANDIDATES: usize = 3;
+// Grid spacing of the shared ephemeris and its extent beyond the searched
+// times, besides the orbit searched back for the ascending node
+const EPHEMERIS_STEP_SEC: f64 = 60.0;
+const EPHEMERIS_MARGIN_SEC: f64 = 180.0;
+// Searched ranges closer than this share one ephemeris
+const EPHEMERIS_MAX_GAP_SEC: f64 = 86400.0;
+
+/// Element sets of one satellite with their initialized orbits
+pub struct Satellite {
+    pub tle_manager: TLEManager,
+    /// `orbitals[i]` is the orbit of `tle_manager.tles[i]`, None if SGP4
+    /// rejected the elements
+    pub orbitals: Vec<Option<Orbital>>,
+}
+
+impl Satellite {
+    /// Initialize every element set. Sets that cannot be used are kept as
+    /// None and their errors returned alongside. Revolutions continue the
+    /// count of the previous set of the same satellite where the 5-digit
+    /// TLE revolution field wrapped past 99999.
+    pub fn new(tle_manager: TLEManager) -> (Self, Vec<OrbitalError>) {
+        let mut errors = Vec::new();
+        let mut orbitals: Vec<Option<Orbital>> = tle_manager
+            .tles
+            .iter()
+            .map(|tle| Orbital::new(tle).map_err(|err| errors.push(err)).ok())
+            .collect();
+        // Offset of each set, from the last earlier set of the same satellite
+        let mut offsets = vec![0; orbitals.len()];
+        let mut last: HashMap<u64, (&Orbital, u64)> = HashMap::new();
+        for (k, orbital) in orbitals.iter().enumerate() {
+            let Some(orbital) = orbital else {
+                continue;
+            };
+            let epoch = orbital.epoch();
+            if let Some((last_orbital, last_offset)) = last.get(&orbital.norad_id())
+                && let (Ok(expected), Ok(counted)) = (last_orbital.revolution_at(epoch), orbital.revolution_at(epoch))
+            {
+                let behind = (expected.number + last_offset) as f64 - counted.number as f64;
+                offsets[k] = (behind / REVOLUTION_FIELD_WRAP as f64).round().max(0.0) as u64 * REVOLUTION_FIELD_WRAP;
+            }
+            last.insert(orbital.norad_id(), (orbital, offsets[k]));
+        }
+        for (orbital, offset) in orbitals.iter_mut().zip(offsets) {
+            if let Some(orbital) = orbital {
+                orbital.add_revolution_offset(offset);
+            }
+        }
+        (Satellite { tle_manager, orbitals }, errors)
+    }
+
+    /// Count the revolutions of satellite `norad_id` from `offset` more than
+    /// its element sets give, for satellites past 100000 revolutions
+    pub fn add_revolution_offset(&mut self, norad_id: u64, offset: u64) {
+        for orbital in self.orbitals.iter_mut().flatten().filter(|orbital| orbital.norad_id() == norad_id) {
+            orbital.add_revolution_offset(offset);
+        }
+    }
+
+    /// Whether the satellite is in geostationary orbit, judged from its elements
+    pub fn is_geostationary(&self) -> bool {
+        self.orbitals.iter().flatten().any(|orbital| orbital.is_geostationary())
+    }
+
+    /// States for `find_tc_passes` searching [`start_utc`, `stop_utc`],
+    /// propagated once and shared by every track searched in that time.
+    /// None for geostationary satellites, whose scans need no ephemeris.
+    pub fn ephemeris(&self, start_utc: f64, stop_utc: f64) -> Option<Ephemeris<'_>> {
+        if self.is_geostationary() {
+            return None;
+        }
+        let period = self
+            .orbitals
+            .iter()
+            .flatten()
+            .map(|orbital| orbital.mean_elements().period_sec)
+            .fold(0.0, f64::max);
+        let margin = EPHEMERIS_MARGIN_SEC + period;
+        Some(Ephemeris::from_tles(
+            &self.tle_manager,
+            &self.orbitals,
+            start_utc - margin,
+            stop_utc + margin,
+            EPHEMERIS_STEP_SEC,
+            MAX_TLE_CANDIDATES,
+        ))
+    }
+
+    /// Ephemerides for searching each of `ranges`, with the range each one
+    /// serves. Ranges within a day of each other share one, so tracks years
+    /// apart are not propagated through the years between. Empty for
+    /// geostationary satellites.
+    pub fn ephemerides(&self, ranges: &[(f64, f64)]) -> Vec<((f64, f64), Ephemeris<'_>)> {
+        let mut ranges = ranges.to_vec();
+        ranges.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut merged: Vec<(f64, f64)> = Vec::new();
+        for (start_utc, stop_utc) in ranges {
+            match merged.last_mut() {
+                Some(last) if start_utc - last.1 <= EPHEMERIS_MAX_GAP_SEC => last.1 = last.1.max(stop_utc),
+                _ => merged.push((start_utc, stop_utc)),
+            }
+        }
+        merged
+            .into_iter()
+            .filter_map(|(start_utc, stop_utc)| Some(((start_utc, stop_utc), self.ephemeris(start_utc, stop_utc)?)))
+            .collect()
+    }
+}
+
+/// Criteria for passes over a storm
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchParams {
+    /// Length of the window searched from each track point, in seconds
+    pub step_sec: f64,
+    /// Minimum storm intensity at CPA, in kt, ignored for tracks without intensity
+    pub min_intensity: f64,
+    /// Maximum distance from the storm center to the sub-satellite point, in km
+    pub max_distance: f64,
+    /// Keep only passes on this node
+    pub node: Option<OrbitNode>,
+    /// Scan schedule assumed for geostationary satellites
+    pub geo_schedule: GeoScanSchedule,
+    /// Maximum satellite zenith angle at the storm for geostationary scans
+    pub geo_max_zenith: f64,
+    /// Radius around the storm center in km over which the time the swath
+    /// covers the storm is found, 0 to skip
+    pub footprint_radius: f64,
+    /// Reach of the swath to either side of the ground track in km
+    pub swath_half_width: f64,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        SearchParams {
+            step_sec: 6.0 * 3600.0,
+            min_intensity: 100.0,
+            max_distance: 1165.0,
+            node: None,
+            geo_schedule: GeoScanSchedule {
+                interval_sec: 600.0,
+                duration_sec: 600.0,
+                south_to_north: false,
+                sector: None,
+            },
+            geo_max_zenith: 80.0,
+            footprint_radius: 0.0,
+            swath_half_width: 1165.0,
+        }
+    }
+}
+
+/// Passes found over a storm
+#[derive(Debug, Default)]
+pub struct SearchResult {
+    /// Passes in time order, each reported once
+    pub events: Vec<TCSatPassEvent>,
+    /// Start times of the windows that could not be searched, with the reason
+    pub skipped: Vec<(f64, OrbitalError)>,
+}
+
+/// Times searched along `track`: its time range, or one window centered on
+/// a track of one instant such as a single fix. None if the track is empty.
+pub fn search_range<T: Track + ?Sized>(track: &T, params: &SearchParams) -> Option<(f64, f64)> {
+    let (track_start, track_end) = track.time_range()?;
+    if track_end > track_start {
+        Some((track_start, track_end))
+    } else {
+        Some((track_start - params.step_sec / 2.0, track_end + params.step_sec / 2.0))
+    }
+}
+
+/// Search `track` in windows of `params.step_sec` for passes of `satellite`
+/// meeting `params`. LEO windows are searched on `ephemeris`, built with
+/// `Satellite::ephemeris` over the `search_range` of the track, and windows
+/// it cannot provide are skipped with its error. Without an ephemeris each
+/// window uses the element set nearest in epoch and falls back to the next
+/// ones when propagation fails.
+pub fn find_tc_passes<T: Track + Sync + ?Sized>(
+    satellite: &Satellite,
+    ephemeris: Option<&Ephemeris>,
+    track: &T,
+    params: &SearchParams,
+) -> SearchResult {
+    let Satellite { tle_manager, orbitals } = satellite;
+    let (Some((track_start, track_end)), Some((search_start, search_end))) =
+        (track.time_range(), search_range(track, params))
+    else {
+        return SearchResult::default();
+    };
+    // Target position at any time in the track, held at the ends. A track
+    // without a position inside its range ends the window there.
+    let position_at = |t: f64| track.position_at(t.clamp(track_start, track_end));
+    // Target attributes and target-relative geometry of a pass found with `orbital`
+    let tc_event = |orbital: &Orbital, pass_event: SatPassEvent| {
+        let track_time = pass_event.cpa_time.clamp(track_start, track_end);
+        let Some((lat_i, lon_i)) = track.position_at(track_time) else {
+            return Ok(None);
+        };
+        let attributes = track.attributes_at(track_time);
+        if attributes.intensity.is_some_and(|intensity| intensity < params.min_intensity) {
+            return Ok(None);
+        }
+        // Geostationary scans are already limited by zenith angle
+        let keep = pass_event.scan_start.is_some()
+            || (pass_event.cpa_distance <= params.max_distance
+                && params.node.is_none_or(|node| Some(node) == pass_event.node));
+        if !keep {
+            return Ok(None);
+        }
+        // Passes are found at sea level, elevated targets see them at another angle
+        let altitude_km = track.altitude_at(track_time);
+        let sat_zenith = if altitude_km == 0.0 {
+            90.0 - pass_event.elevation
+        } else {
+            orbital.zenith_angle_at(pass_event.cpa_time, lat_i, lon_i, altitude_km)?
+        };
+        let heading = attributes.heading;
+        let footprint = if pass_event.scan_start.is_some() || params.footprint_radius <= 0.0 {
+            None
+        } else {
+            orbital.footprint_interval(
+                pass_event.cpa_time,
+                TRACK_HALF_WINDOW_SEC,
+                lon_i,
+                lat_i,
+                params.swath_half_width,
+                params.footprint_radius,
+            )?
+        };
+        let (cpa_relative, track) = if pass_event.scan_start.is_some() {
+            (None, Vec::new())
+        } else {
+            (
+                Some(orbital.storm_relative_point(pass_event.cpa_time, lon_i, lat_i, heading)?),
+                orbital.storm_relative_track(
+                    pass_event.cpa_time,
+                    TRACK_HALF_WINDOW_SEC,
+                    TRACK_STEP_SEC,
+                    lon_i,
+                    lat_i,
+                    heading,
+                )?,
+            )
+        };
+        Ok::<_, OrbitalError>(Some(TCSatPassEvent {
+            cpa_time: pass_event.cpa_time,
+            cpa_distance: pass_event.cpa_distance,
+            sat_zenith,
+            intensity: attributes.intensity,
+            storm_latitude: lat_i,
+            storm_longitude: lon_i,
+            norad_id: orbital.norad_id(),
+            tle_epoch: orbital.epoch(),
+            node: pass_event.node,
+            orbit_number: pass_event.orbit_number,
+            orbit_start: pass_event.orbit_start,
+            orbit_period: pass_event.orbit_period,
+            local_solar_time: pass_event.local_solar_time,
+            equator_crossing_time: pass_event.equator_crossing_time,
+            scan_start: pass_event.scan_start,
+            scan_area: pass_event.scan_area,
+            footprint_start: footprint.map(|(start, _)| start),
+            footprint_end: footprint.map(|(_, end)| end),
+            cpa_relative,
+            track,
+        }))
+    };
+    // Passes in [time, time + interval_sec) from one element set
+    let orbital_passes = |orbital: &Orbital, time: f64, interval_sec: f64| {
+        let pass_events = if orbital.is_geostationary() {
+            orbital.get_geo_scans(
+                time,
+                interval_sec,
+                position_at,
+                &params.geo_schedule,
+                params.geo_max_zenith,
+            )?
+        } else {
+            orbital.get_passes(time, interval_sec, position_at)?
+        };
+        pass_events
+            .into_iter()
+            .filter_map(|pass_event| tc_event(orbital, pass_event).transpose())
+            .collect::<Result<Vec<_>, OrbitalError>>()
+    };
+    // Passes in [time, time + interval_sec) from the shared LEO states
+    let ephemeris_passes = |ephemeris: &Ephemeris, time: f64, interval_sec: f64| {
+        ephemeris
+            .get_passes(time, interval_sec, position_at)?
+            .into_iter()
+            .filter_map(|pass_event| {
+                ephemeris
+                    .orbital_at(pass_event.cpa_time)
+                    .and_then(|orbital| tc_event(orbital, pass_event))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, OrbitalError>>()
+    };
+    let window_passes = |time: f64| -> Result<Vec<TCSatPassEvent>, OrbitalError> {
+        let interval_sec = params.step_sec.min(search_end - time);
+        if interval_sec <= 0.0 {
+            return Ok(Vec::new());
+        }
+        if let Some(ephemeris) = ephemeris {
+            return ephemeris_passes(ephemeris, time, interval_sec);
+        }
+        let mut propagation_error = None;
+        for tle_index in tle_manager.nearest_tle_indices(time, MAX_TLE_CANDIDATES) {
+            let Some(orbital) = &orbitals[tle_index] else {
+                continue;
+            };
+            match orbital_passes(orbital, time, interval_sec) {
+                Err(err) if err.is_propagation_failure() => {
+                    // Elements from another epoch may still propagate
+                    propagation_error = Some(err);
+                }
+                result => return result,
+            }
+        }
+        propagation_error.map_or(Ok(Vec::new()), Err)
+    };
+    let n_windows = ((search_end - search_start) / params.step_sec).ceil().max(1.0) as usize;
+    let windows: Vec<_> = (0..n_windows)
+        .into_par_iter()
+        .map(|k| {
+            let time = search_start + k as f64 * params.step_sec;
+            (time, window_passes(time))
+        })
+        .collect();
+    let mut result = SearchResult::default();
+    for (time, window) in windows {
+        match window {
+            Ok(events) => result.events.extend(events),
+            Err(err) => result.skipped.push((time, err)),
+        }
+    }
+    dedup_passes(&mut result.events);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tle::{fixtures, TLE};
+    use crate::track::{BDeck, FixedTrack};
+
+    #[test]
+    fn test_single_fix_track() {
+        let (satellite, _) = Satellite::new(TLEManager { tles: vec![fixtures::aqua()] });
+        // One fix right under the satellite ten minutes after epoch
+        let fix_time = satellite.tle_manager.tles[0].epoch() + 600.0;
+        let below = satellite.orbitals[0].as_ref().unwrap().propagate(fix_time).unwrap();
+        let bdeck = BDeck {
+            storm_id: "WP312013".to_string(),
+            time: vec![fix_time],
+            intensity: vec![120.0],
+            latitude: vec![below.latitude],
+            longitude: vec![below.longitude],
+        };
+        let site = FixedTrack {
+            latitude: below.latitude,
+            longitude: below.longitude,
+            altitude_km: 0.0,
+            start_utc: fix_time,
+            stop_utc: fix_time,
+        };
+        for track in [&bdeck as &(dyn Track + Sync), &site] {
+            let params = SearchParams::default();
+            let (start_utc, stop_utc) = search_range(track, &params).unwrap();
+            let ephemeris = satellite.ephemeris(start_utc, stop_utc);
+            let result = find_tc_passes(&satellite, ephemeris.as_ref(), track, &params);
+            assert!(result.skipped.is_empty());
+            let pass = result.events.iter().find(|event| (event.cpa_time - fix_time).abs() < 1.0);
+            assert!(pass.is_some_and(|pass| pass.cpa_distance < 1.0), "{:?}", result.events);
+        }
+    }
+
+    #[test]
+    fn test_baseline_passes() {
+        // CPA times and distances the observer-based pass search this one
+        // replaced finds for Haiyan. The element sets are one Aqua orbit
+        // restated daily at 12:00, agreeing to within 50 m, so the old choice
+        // of elements by fix time and the new one by CPA time give the same
+        // passes.
+        let baseline = [
+            (1383667725.2, 186.4), // 2013-11-05 16:08:45
+            (1383712557.8, 991.6), // 2013-11-06 04:35:57
+            (1383756674.0, 770.9), // 2013-11-06 16:51:13
+            (1383795707.8, 944.5), // 2013-11-07 03:41:47
+            (1383884708.0, 283.5), // 2013-11-08 04:25:07
+            (1383928725.2, 641.6), // 2013-11-08 16:38:45
+        ];
+        let tle_manager =
+            TLEManager::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/aqua_27424_2013.tle")).unwrap();
+        let (satellite, _) = Satellite::new(tle_manager);
+        let bdeck = BDeck::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/bwp312013.dat")).unwrap();
+        let params = SearchParams::default();
+        let (start_utc, stop_utc) = search_range(&bdeck, &params).unwrap();
+        let ephemeris = satellite.ephemeris(start_utc, stop_utc);
+        // Through the shared ephemeris and by direct propagation
+        for ephemeris in [ephemeris.as_ref(), None] {
+            let result = find_tc_passes(&satellite, ephemeris, &bdeck, &params);
+            assert!(result.skipped.is_empty());
+            assert_eq!(result.events.len(), baseline.len(), "{:?}", result.events);
+            for (event, (cpa_time, cpa_distance)) in result.events.iter().zip(baseline) {
+                assert!((event.cpa_time - cpa_time).abs() <= 1.0, "{} {}", event.cpa_time, cpa_time);
+                assert!((event.cpa_distance - cpa_distance).abs() <= 1.0, "{} {}", event.cpa_distance, cpa_distance);
+            }
+        }
+    }
+
+    #[test]
+    fn test_node_filter() {
+        let tle_manager =
+            TLEManager::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/aqua_27424_2013.tle")).unwrap();
+        let (satellite, _) = Satellite::new(tle_manager);
+        let bdeck = BDeck::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/bwp312013.dat")).unwrap();
+        let (start_utc, stop_utc) = search_range(&bdeck, &SearchParams::default()).unwrap();
+        let ephemeris = satellite.ephemeris(start_utc, stop_utc);
+        let cpa_times = |node: Option<OrbitNode>| -> Vec<f64> {
+            let params = SearchParams { node, ..SearchParams::default() };
+            let result = find_tc_passes(&satellite, ephemeris.as_ref(), &bdeck, &params);
+            assert!(result.events.iter().all(|event| node.is_none_or(|node| event.node == Some(node))));
+            result.events.iter().map(|event| event.cpa_time).collect()
+        };
+        // Aqua crosses the storm ascending in the afternoon and descending
+        // after midnight, local time
+        let all = cpa_times(None);
+        let ascending = cpa_times(Some(OrbitNode::Ascending));
+        let descending = cpa_times(Some(OrbitNode::Descending));
+        assert_eq!((ascending.len(), descending.len()), (3, 3));
+        let mut both = [ascending, descending].concat();
+        both.sort_by(f64::total_cmp);
+        assert_eq!(both, all);
+    }
+
+    #[test]
+    fn test_revolution_field_wrap() {
+        // Revolution 99990 at the first epoch; a day later the field has
+        // wrapped to 4
+        let tles = vec![
+            TLE::new(
+                "1 27424U 02022A   13303.50000000  .00000100  00000-0  32000-4 0  9990",
+                "2 27424  98.2045 245.1234 0000151  85.3000 123.4000 14.57106000999903",
+            )
+            .unwrap(),
+            TLE::new(
+                "1 27424U 02022A   13304.50000000  .00000100  00000-0  32000-4 0  9991",
+                "2 27424  98.2045 245.1234 0000151  85.3000 123.4000 14.57106000000041",
+            )
+            .unwrap(),
+        ];
+        let (mut satellite, _) = Satellite::new(TLEManager { tles });
+        let number_at_epoch = |satellite: &Satellite, k: usize| {
+            let orbital = satellite.orbitals[k].as_ref().unwrap();
+            orbital.revolution_at(orbital.epoch()).unwrap().number
+        };
+        assert_eq!(number_at_epoch(&satellite, 0), 99990);
+        assert_eq!(number_at_epoch(&satellite, 1), 100004);
+
+        satellite.add_revolution_offset(27424, 200_000);
+        satellite.add_revolution_offset(25994, 100_000);
+        assert_eq!(number_at_epoch(&satellite, 0), 299990);
+        assert_eq!(number_at_epoch(&satellite, 1), 300004);
+    }
+
+    #[test]
+    fn test_footprint_swath() {
+        let (satellite, _) = Satellite::new(TLEManager { tles: vec![fixtures::aqua()] });
+        let fix_time = satellite.tle_manager.tles[0].epoch() + 600.0;
+        let below = satellite.orbitals[0].as_ref().unwrap().propagate(fix_time).unwrap();
+        // About 600 km east of the near-polar ground track
+        let site = FixedTrack {
+            latitude: below.latitude,
+            longitude: below.longitude + 600.0 / (111.3 * below.latitude.to_radians().cos()),
+            altitude_km: 0.0,
+            start_utc: fix_time,
+            stop_utc: fix_time,
+        };
+        let footprint = |swath_half_width: f64| {
+            let params = SearchParams { footprint_radius: 300.0, swath_half_width, ..SearchParams::default() };
+            let result = find_tc_passes(&satellite, None, &site, &params);
+            let pass = result.events.iter().find(|event| (event.cpa_time - fix_time).abs() < 60.0).unwrap();
+            pass.footprint_start.zip(pass.footprint_end)
+        };
+        // The swath, not the distance cutoff, decides whether the storm is covered
+        assert!(footprint(1000.0).is_some_and(|(start, end)| start < fix_time && fix_time < end));
+        assert!(footprint(100.0).is_none());
+    }
+}
@@ -1,44 +1,101 @@
+//! Two-line element sets
+
+use std::path::PathBuf;
+
 use chrono::{NaiveDate, Duration};
 
-fn tle_epoch_to_timestamp(tle_epoch: &str) -> f64 {
-    let year: i32 = tle_epoch[0..2].parse().unwrap();
+use crate::orbital::OrbitalError;
+
+// Unix time of the `YYDDD.DDDDDDDD` epoch field, None if it is no date
+fn tle_epoch_to_timestamp(tle_epoch: &str) -> Option<f64> {
+    let year: i32 = tle_epoch.get(0..2)?.trim().parse().ok()?;
     let year_full = if year < 57 { 2000 + year } else { 1900 + year };
-    let day_of_year: f64 = tle_epoch[2..].parse().unwrap();
+    let day_of_year: f64 = tle_epoch.get(2..)?.trim().parse().ok()?;
 
-    let naive_date = NaiveDate::from_yo_opt(year_full, day_of_year.floor() as u32).unwrap();
-    let seconds_in_day = ((day_of_year - day_of_year.floor()) * 86400.0).round() as u32;
-    let naive_datetime = naive_date.and_hms_opt(0, 0, 0).unwrap()
-        .checked_add_signed(Duration::seconds(seconds_in_day as i64)).unwrap();
+    let naive_date = NaiveDate::from_yo_opt(year_full, day_of_year.floor() as u32)?;
+    let seconds_in_day = ((day_of_year - day_of_year.floor()) * 86400.0).round() as i64;
+    let naive_datetime = naive_date.and_hms_opt(0, 0, 0)?
+        .checked_add_signed(Duration::seconds(seconds_in_day))?;
 
     let datetime_utc = naive_datetime.and_utc();
-    datetime_utc.timestamp() as f64
+    Some(datetime_utc.timestamp() as f64)
 }
 
+/// One two-line element set
+#[allow(clippy::upper_case_acronyms)]
 pub struct TLE {
     pub line1: String,
     pub line2: String,
     epoch_timestamp: f64,
 }
 
+impl TLE {
+    /// Element set from its two lines, without the optional title line. Only
+    /// the epoch is read here, the other fields are checked by `Orbital::new`.
+    pub fn new(line1: &str, line2: &str) -> Result<Self, OrbitalError> {
+        let epoch_timestamp = line1
+            .get(18..32)
+            .and_then(tle_epoch_to_timestamp)
+            .ok_or(OrbitalError::InvalidTle { source: None })?;
+        Ok(TLE {
+            line1: line1.to_string(),
+            line2: line2.to_string(),
+            epoch_timestamp,
+        })
+    }
+
+    /// Epoch in unix seconds
+    pub fn epoch(&self) -> f64 {
+        self.epoch_timestamp
+    }
+}
+
+/// Unusable TLE file
+#[derive(Debug)]
+pub enum TleFileError {
+    Read { path: PathBuf, source: std::io::Error },
+    /// The element set starting on line `line` cannot be read
+    Invalid { path: PathBuf, line: usize, source: OrbitalError },
+}
+
+impl std::fmt::Display for TleFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TleFileError::Read { path, source } => write!(f, "{}: {}", path.display(), source),
+            TleFileError::Invalid { path, line, source } => {
+                write!(f, "{}: line {}: {}", path.display(), line, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TleFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TleFileError::Read { source, .. } => Some(source),
+            TleFileError::Invalid { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Element sets of one satellite ordered by epoch
 pub struct TLEManager {
     pub tles: Vec<TLE>,
 }
 
 impl TLEManager {
-    pub fn from_file(filepath: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let content = std::fs::read_to_string(filepath)?;
+    /// Read consecutive line pairs of a TLE file, without title lines. Blank
+    /// lines are skipped.
+    pub fn from_file(filepath: &str) -> Result<Self, TleFileError> {
+        let path = PathBuf::from(filepath);
+        let content = std::fs::read_to_string(&path)
+            .map_err(|source| TleFileError::Read { path: path.clone(), source })?;
         let mut tles = Vec::new();
-        let mut lines = content.lines();
-        while let (Some(line1), Some(line2)) = (lines.next(), lines.next()) {
-            if line1.len() >= 32 {
-                let tle_epoch = &line1[18..32];
-                let epoch_timestamp = tle_epoch_to_timestamp(tle_epoch);
-                tles.push(TLE {
-                    line1: line1.to_string(),
-                    line2: line2.to_string(),
-                    epoch_timestamp,
-                });
-            }
+        let mut lines = content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        while let (Some((n, line1)), Some((_, line2))) = (lines.next(), lines.next()) {
+            let tle = TLE::new(line1, line2)
+                .map_err(|source| TleFileError::Invalid { path: path.clone(), line: n + 1, source })?;
+            tles.push(tle);
         }
         tles.sort_by(|a, b| {
             a.epoch_timestamp
@@ -48,6 +105,36 @@ impl TLEManager {
         Ok(TLEManager { tles })
     }
 
+    /// Up to `count` TLE indices ordered from the nearest epoch outwards
+    pub fn nearest_tle_indices(&self, target_time: f64, count: usize) -> Vec<usize> {
+        let Some(nearest) = self.select_tle_index(target_time) else {
+            return Vec::new();
+        };
+        let distance = |index: usize| (self.tles[index].epoch_timestamp - target_time).abs();
+        let mut indices = vec![nearest];
+        // Next candidates on either side of the ones already taken
+        let (mut before, mut after) = (nearest, nearest + 1);
+        while indices.len() < count {
+            let prev = before.checked_sub(1);
+            let next = (after < self.tles.len()).then_some(after);
+            let take_prev = match (prev, next) {
+                (Some(p), Some(n)) => distance(p) <= distance(n),
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            if take_prev {
+                before -= 1;
+                indices.push(before);
+            } else {
+                indices.push(after);
+                after += 1;
+            }
+        }
+        indices
+    }
+
+    /// Index of the TLE with epoch nearest to `target_time`
     pub fn select_tle_index(&self, target_time: f64) -> Option<usize> {
         if self.tles.is_empty() {
             return None;
@@ -86,6 +173,27 @@ impl TLEManager {
 }
 
 
+/// Element sets shared by the tests
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::TLE;
+
+    /// Aqua on revolution 60000 at epoch 2013-10-30 12:00 UTC, also read by
+    /// the benchmarks
+    const AQUA_TLE: &str = include_str!("../testdata/aqua_27424.tle");
+
+    /// The two lines of the Aqua element set
+    pub fn aqua_lines() -> (&'static str, &'static str) {
+        let mut lines = AQUA_TLE.lines();
+        (lines.next().unwrap(), lines.next().unwrap())
+    }
+
+    pub fn aqua() -> TLE {
+        let (line1, line2) = aqua_lines();
+        TLE::new(line1, line2).unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -94,6 +202,33 @@ mod tests {
     fn test_tle_epoch_to_timestamp() {
         let tle_epoch = "23045.5";
         let timestamp = tle_epoch_to_timestamp(tle_epoch);
-        assert_eq!(timestamp, 1676376000.0);
+        assert_eq!(timestamp, Some(1676376000.0));
+        assert_eq!(tle_epoch_to_timestamp("23400.5"), None);
+        assert_eq!(tle_epoch_to_timestamp("2x045.5"), None);
+    }
+
+    #[test]
+    fn test_tle_new() {
+        let (_, line2) = fixtures::aqua_lines();
+        let tle = fixtures::aqua();
+        assert_eq!(tle.epoch(), 1383134400.0);
+        // Truncated lines and epochs that are no date are errors, not panics
+        assert!(matches!(TLE::new("1 27424U 02022A   1330", line2), Err(OrbitalError::InvalidTle { .. })));
+        assert!(TLE::new("1 27424U 02022A   13ABC.50000000  .00000100", line2).is_err());
+    }
+
+    #[test]
+    fn test_nearest_tle_indices() {
+        let tles = [0.0, 100.0, 200.0, 300.0]
+            .iter()
+            .map(|&epoch_timestamp| TLE {
+                line1: String::new(),
+                line2: String::new(),
+                epoch_timestamp,
+            })
+            .collect();
+        let manager = TLEManager { tles };
+        assert_eq!(manager.nearest_tle_indices(180.0, 3), vec![2, 1, 3]);
+        assert_eq!(manager.nearest_tle_indices(-50.0, 10), vec![0, 1, 2, 3]);
     }
 }
@@ -0,0 +1,629 @@
+//! Storm tracks and other moving targets
+
+use std::fs::read_to_string;
+use std::io;
+use std::path::PathBuf;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+use geographiclib_rs::{Geodesic, InverseGeodesic};
+
+/// Time-ordered positions of a moving target that drive the pass search.
+/// Times are unix seconds and positions degrees, longitude east.
+pub trait Track {
+    /// First and last time of the track, None if it is empty
+    fn time_range(&self) -> Option<(f64, f64)>;
+
+    /// (latitude, longitude) at `time`, None outside the track
+    fn position_at(&self, time: f64) -> Option<(f64, f64)>;
+
+    /// Intensity and motion at `time`, as far as the track knows them
+    fn attributes_at(&self, time: f64) -> TrackAttributes;
+
+    /// Height of the target above the ellipsoid at `time`, in km
+    fn altitude_at(&self, _time: f64) -> f64 {
+        0.0
+    }
+}
+
+/// Target attributes used to filter and describe passes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TrackAttributes {
+    /// Maximum sustained wind in kt
+    pub intensity: Option<f64>,
+    /// Direction of motion, degrees clockwise from north
+    pub heading: Option<f64>,
+    /// Speed of motion in kt
+    pub speed: Option<f64>,
+}
+
+// Heading and speed on the track segment containing `query_time`
+fn segment_motion(
+    time: &[f64],
+    latitude: &[f64],
+    longitude: &[f64],
+    query_time: f64,
+) -> Option<(f64, f64)> {
+    if time.len() < 2 {
+        return None;
+    }
+    if query_time < time[0] || query_time > *time.last().unwrap() {
+        return None;
+    }
+    let i = time
+        .partition_point(|t| *t <= query_time)
+        .saturating_sub(1)
+        .min(time.len() - 2);
+    let geod = Geodesic::wgs84();
+    let (s12, azi1, _, _): (f64, f64, f64, f64) =
+        geod.inverse(latitude[i], longitude[i], latitude[i + 1], longitude[i + 1]);
+    let hours = (time[i + 1] - time[i]) / 3600.0;
+    Some((azi1.rem_euclid(360.0), s12 / 1852.0 / hours))
+}
+
+/// Best track from an ATCF B-Deck file, one fix per synoptic time. Times are
+/// unix seconds, intensity in kt and longitude in degrees east [0, 360).
+pub struct BDeck {
+    /// Basin, cyclone number and year, e.g. WP312013
+    pub storm_id: String,
+    pub time: Vec<f64>,
+    pub intensity: Vec<f64>,
+    pub latitude: Vec<f64>,
+    pub longitude: Vec<f64>,
+}
+
+impl BDeck {
+    /// Read the 00/06/12/18 UTC fixes of a B-Deck file, first wind radii line
+    /// only. Blank lines are skipped.
+    pub fn from_file(filepath: &str) -> Result<Self, InputError> {
+        let mut time = Vec::new();
+        let mut intensity = Vec::new();
+        let mut latitude = Vec::new();
+        let mut longitude = Vec::new();
+
+        let file = read_input(filepath)?;
+        let storm_id = file.lines().next().map_or_else(String::new, |line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let year = fields.get(2).and_then(|t| t.get(..4)).unwrap_or("");
+            format!("{}{}{}", fields[0], fields.get(1).unwrap_or(&""), year)
+        });
+        let mut last_time = "";
+        for (n, line) in file.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let bad_row = || InputError::invalid(filepath, format!("bad row {}: {}", n + 1, line));
+            if !line.is_ascii() {
+                return Err(bad_row());
+            }
+            let field = |range: std::ops::Range<usize>| line.get(range).ok_or_else(bad_row);
+            let line_time = field(8..18)?;
+            let line_hour: i32 = line_time[8..10].parse().map_err(|_| bad_row())?;
+            if line_hour % 6 != 0 {
+                continue;
+            }
+            if last_time == line_time {
+                continue;
+            }
+            last_time = line_time;
+            let timestamp = NaiveDateTime::parse_from_str(
+                &format!("{}{}", line_time, "00"), "%Y%m%d%H%M",
+            ).map_err(|_| bad_row())?.and_utc().timestamp() as f64;
+            let line_len = line.len() - 1;
+            let temp_wind = if line_len < 51 {
+                // Fix case that a space is missing in short-style bdeck
+                &line[line_len - 3..]
+            } else {
+                field(48..51)?
+            };
+            let mut wind: i32 = temp_wind
+                .strip_prefix(" ")
+                .unwrap_or(temp_wind)
+                .parse()
+                .unwrap_or(0);
+            if wind == 999 {
+                wind = 0;
+            }
+            let lat_str = field(35..39)?;
+            let lat_string: String = lat_str[..3]
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            let mut lat: f32 = lat_string.parse::<f32>().map_err(|_| bad_row())? / 10.;
+            if &lat_str[3..4] == "S" {
+                lat *= -1.
+            }
+            let lon_str = field(41..46)?;
+            let lon_string: String = lon_str[..4]
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            let mut lon: f32 = lon_string.parse::<f32>().map_err(|_| bad_row())? / 10.;
+            if &lon_str[4..5] == "W" {
+                lon = 360. - lon;
+            }
+            time.push(timestamp);
+            intensity.push(wind as f64);
+            latitude.push(lat as f64);
+            longitude.push(lon as f64);
+        }
+
+        Ok(BDeck {
+            storm_id,
+            time,
+            intensity,
+            latitude,
+            longitude,
+        })
+    }
+
+    /// Latitude, longitude and intensity linearly interpolated between fixes,
+    /// None outside the track
+    pub fn interpolate(&self, query_time: f64) -> Option<(f64, f64, f64)> {
+        let mut index = self.time.partition_point(|t| *t <= query_time).saturating_sub(1);
+        self.interpolate_with_index(query_time, &mut index)
+    }
+
+    /// As `interpolate`, starting the fix lookup from `index` and updating it,
+    /// for queries in increasing time
+    pub fn interpolate_with_index(
+        &self,
+        query_time: f64,
+        index: &mut usize,
+    ) -> Option<(f64, f64, f64)> {
+        if self.time.is_empty() {
+            return None;
+        }
+        if query_time < self.time[0] || query_time > *self.time.last().unwrap() {
+            return None;
+        }
+
+        let mut i = (*index).min(self.time.len().saturating_sub(1));
+        if query_time < self.time[i] {
+            match self.time.binary_search_by(|t| {
+                t.partial_cmp(&query_time)
+                    .unwrap_or(std::cmp::Ordering::Less)
+            }) {
+                Ok(found) => {
+                    *index = found;
+                    return Some((
+                        self.latitude[found],
+                        self.longitude[found],
+                        self.intensity[found],
+                    ));
+                }
+                Err(found) => {
+                    if found == 0 || found >= self.time.len() {
+                        return None;
+                    }
+                    i = found - 1;
+                }
+            }
+        } else {
+            while i + 1 < self.time.len() && self.time[i + 1] < query_time {
+                i += 1;
+            }
+        }
+
+        if self.time[i] == query_time {
+            *index = i;
+            return Some((self.latitude[i], self.longitude[i], self.intensity[i]));
+        }
+        if i + 1 < self.time.len() && self.time[i + 1] == query_time {
+            *index = i + 1;
+            return Some((
+                self.latitude[i + 1],
+                self.longitude[i + 1],
+                self.intensity[i + 1],
+            ));
+        }
+        if i + 1 >= self.time.len() {
+            return None;
+        }
+
+        let t0 = self.time[i];
+        let t1 = self.time[i + 1];
+        let factor = (query_time - t0) / (t1 - t0);
+
+        let lat = self.latitude[i] + factor * (self.latitude[i + 1] - self.latitude[i]);
+        // Interpolate across the prime meridian the short way
+        let dlon = (self.longitude[i + 1] - self.longitude[i] + 180.0).rem_euclid(360.0) - 180.0;
+        let lon = (self.longitude[i] + factor * dlon).rem_euclid(360.0);
+        let inten = self.intensity[i] + factor * (self.intensity[i + 1] - self.intensity[i]);
+
+        *index = i;
+        Some((lat, lon, inten))
+    }
+
+    /// Heading (degrees clockwise from north) and speed (kt) of the storm on
+    /// the best-track segment containing `query_time`
+    pub fn motion_at(&self, query_time: f64) -> Option<(f64, f64)> {
+        segment_motion(&self.time, &self.latitude, &self.longitude, query_time)
+    }
+}
+
+impl Track for BDeck {
+    fn time_range(&self) -> Option<(f64, f64)> {
+        Some((*self.time.first()?, *self.time.last()?))
+    }
+
+    fn position_at(&self, time: f64) -> Option<(f64, f64)> {
+        self.interpolate(time).map(|(lat, lon, _)| (lat, lon))
+    }
+
+    fn attributes_at(&self, time: f64) -> TrackAttributes {
+        let motion = self.motion_at(time);
+        TrackAttributes {
+            intensity: self.interpolate(time).map(|(_, _, intensity)| intensity),
+            heading: motion.map(|m| m.0),
+            speed: motion.map(|m| m.1),
+        }
+    }
+}
+
+/// Track read from a CSV file with a header line naming the columns `time`,
+/// `latitude` (or `lat`), `longitude` (or `lon`) and optionally `intensity`
+/// in kt. Times are RFC 3339 or `YYYY-MM-DD HH:MM:SS` in UTC, longitudes
+/// west of Greenwich may be negative.
+pub struct CsvTrack {
+    pub time: Vec<f64>,
+    pub latitude: Vec<f64>,
+    pub longitude: Vec<f64>,
+    pub intensity: Option<Vec<f64>>,
+}
+
+/// Unreadable or malformed input file
+#[derive(Debug)]
+pub enum InputError {
+    Read { path: PathBuf, source: io::Error },
+    /// A missing column or a row that cannot be parsed
+    Invalid { path: PathBuf, message: String },
+}
+
+impl InputError {
+    fn invalid(path: &str, message: String) -> Self {
+        InputError::Invalid { path: PathBuf::from(path), message }
+    }
+}
+
+impl std::fmt::Display for InputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputError::Read { path, source } => write!(f, "{}: {}", path.display(), source),
+            InputError::Invalid { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for InputError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InputError::Read { source, .. } => Some(source),
+            InputError::Invalid { .. } => None,
+        }
+    }
+}
+
+// Contents of an input file
+fn read_input(filepath: &str) -> Result<String, InputError> {
+    read_to_string(filepath).map_err(|source| InputError::Read { path: PathBuf::from(filepath), source })
+}
+
+/// Unix time of an RFC 3339 timestamp, or of `YYYY-MM-DD[ HH:MM[:SS]]` in UTC
+pub fn parse_time(field: &str) -> Option<f64> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(field) {
+        return Some(dt.timestamp() as f64);
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(field, "%Y-%m-%d") {
+        return Some(date.and_time(NaiveTime::MIN).and_utc().timestamp() as f64);
+    }
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(field, format).ok())
+        .map(|dt| dt.and_utc().timestamp() as f64)
+}
+
+impl CsvTrack {
+    /// Read a track, rows may be in any time order but no two at the same time
+    pub fn from_file(filepath: &str) -> Result<Self, InputError> {
+        let file = read_input(filepath)?;
+        let mut lines = file.lines().filter(|line| !line.trim().is_empty());
+        let header: Vec<String> = lines
+            .next()
+            .ok_or_else(|| InputError::invalid(filepath, "empty track".to_string()))?
+            .split(',')
+            .map(|name| name.trim().to_lowercase())
+            .collect();
+        let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+        let (Some(time_col), Some(lat_col), Some(lon_col)) = (
+            column(&["time"]),
+            column(&["latitude", "lat"]),
+            column(&["longitude", "lon"]),
+        ) else {
+            return Err(InputError::invalid(
+                filepath,
+                "header needs time, latitude and longitude columns".to_string(),
+            ));
+        };
+        let intensity_col = column(&["intensity"]);
+
+        let mut rows = Vec::new();
+        for (n, line) in lines.enumerate() {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let field = |col: usize| fields.get(col).copied().unwrap_or("");
+            let bad_row = || InputError::invalid(filepath, format!("bad row {}: {}", n + 2, line));
+            let time = parse_time(field(time_col)).ok_or_else(bad_row)?;
+            let lat: f64 = field(lat_col).parse().map_err(|_| bad_row())?;
+            let lon: f64 = field(lon_col).parse().map_err(|_| bad_row())?;
+            let intensity = match intensity_col {
+                Some(col) => Some(field(col).parse::<f64>().map_err(|_| bad_row())?),
+                None => None,
+            };
+            rows.push((time, lat, lon.rem_euclid(360.0), intensity, n + 2));
+        }
+        rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+        // Interpolation divides by the time between neighbouring rows
+        if let Some(pair) = rows.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            let (first, second) = (pair[0].4.min(pair[1].4), pair[0].4.max(pair[1].4));
+            return Err(InputError::invalid(
+                filepath,
+                format!("rows {} and {} have the same time", first, second),
+            ));
+        }
+
+        Ok(CsvTrack {
+            time: rows.iter().map(|row| row.0).collect(),
+            latitude: rows.iter().map(|row| row.1).collect(),
+            longitude: rows.iter().map(|row| row.2).collect(),
+            intensity: intensity_col.map(|_| rows.iter().filter_map(|row| row.3).collect()),
+        })
+    }
+
+    // Index of the segment containing `time` and the fraction along it
+    fn segment(&self, time: f64) -> Option<(usize, f64)> {
+        let (start, stop) = self.time_range()?;
+        if time < start || time > stop {
+            return None;
+        }
+        if self.time.len() == 1 {
+            return Some((0, 0.0));
+        }
+        let i = self
+            .time
+            .partition_point(|t| *t <= time)
+            .saturating_sub(1)
+            .min(self.time.len() - 2);
+        Some((i, (time - self.time[i]) / (self.time[i + 1] - self.time[i])))
+    }
+}
+
+impl Track for CsvTrack {
+    fn time_range(&self) -> Option<(f64, f64)> {
+        Some((*self.time.first()?, *self.time.last()?))
+    }
+
+    fn position_at(&self, time: f64) -> Option<(f64, f64)> {
+        let (i, factor) = self.segment(time)?;
+        if factor == 0.0 {
+            return Some((self.latitude[i], self.longitude[i]));
+        }
+        let lat = self.latitude[i] + factor * (self.latitude[i + 1] - self.latitude[i]);
+        // Interpolate across the antimeridian the short way
+        let dlon = (self.longitude[i + 1] - self.longitude[i] + 180.0).rem_euclid(360.0) - 180.0;
+        let lon = (self.longitude[i] + factor * dlon).rem_euclid(360.0);
+        Some((lat, lon))
+    }
+
+    fn attributes_at(&self, time: f64) -> TrackAttributes {
+        let motion = segment_motion(&self.time, &self.latitude, &self.longitude, time);
+        let intensity = self.intensity.as_ref().and_then(|intensity| {
+            let (i, factor) = self.segment(time)?;
+            if factor == 0.0 {
+                return Some(intensity[i]);
+            }
+            Some(intensity[i] + factor * (intensity[i + 1] - intensity[i]))
+        });
+        TrackAttributes {
+            intensity,
+            heading: motion.map(|m| m.0),
+            speed: motion.map(|m| m.1),
+        }
+    }
+}
+
+/// Named fixed location such as a radar station, buoy or field campaign base
+#[derive(Debug, Clone)]
+pub struct Site {
+    pub name: String,
+    pub latitude: f64,
+    /// Degrees east, [0, 360)
+    pub longitude: f64,
+    /// Height above the ellipsoid in m
+    pub altitude: f64,
+}
+
+impl Site {
+    /// Read a CSV site list with a header line naming the columns `name`,
+    /// `latitude` (or `lat`), `longitude` (or `lon`) and optionally `altitude`
+    /// (or `alt`) in m
+    pub fn read_list(filepath: &str) -> Result<Vec<Site>, InputError> {
+        let file = read_input(filepath)?;
+        let mut lines = file.lines().filter(|line| !line.trim().is_empty());
+        let header: Vec<String> = lines
+            .next()
+            .ok_or_else(|| InputError::invalid(filepath, "empty site list".to_string()))?
+            .split(',')
+            .map(|name| name.trim().to_lowercase())
+            .collect();
+        let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+        let (Some(name_col), Some(lat_col), Some(lon_col)) = (
+            column(&["name"]),
+            column(&["latitude", "lat"]),
+            column(&["longitude", "lon"]),
+        ) else {
+            return Err(InputError::invalid(
+                filepath,
+                "header needs name, latitude and longitude columns".to_string(),
+            ));
+        };
+        let alt_col = column(&["altitude", "alt"]);
+
+        let mut sites = Vec::new();
+        for (n, line) in lines.enumerate() {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let field = |col: usize| fields.get(col).copied().unwrap_or("");
+            let bad_row = || InputError::invalid(filepath, format!("bad row {}: {}", n + 2, line));
+            let latitude: f64 = field(lat_col).parse().map_err(|_| bad_row())?;
+            let longitude: f64 = field(lon_col).parse().map_err(|_| bad_row())?;
+            let altitude = match alt_col.map(field) {
+                Some(alt) if !alt.is_empty() => alt.parse().map_err(|_| bad_row())?,
+                _ => 0.0,
+            };
+            sites.push(Site {
+                name: field(name_col).to_string(),
+                latitude,
+                longitude: longitude.rem_euclid(360.0),
+                altitude,
+            });
+        }
+        Ok(sites)
+    }
+
+    /// The site as a stationary target over [`start_utc`, `stop_utc`]
+    pub fn track(&self, start_utc: f64, stop_utc: f64) -> FixedTrack {
+        FixedTrack {
+            latitude: self.latitude,
+            longitude: self.longitude,
+            altitude_km: self.altitude / 1000.0,
+            start_utc,
+            stop_utc,
+        }
+    }
+}
+
+/// Stationary target over a time range
+#[derive(Debug, Clone)]
+pub struct FixedTrack {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_km: f64,
+    pub start_utc: f64,
+    pub stop_utc: f64,
+}
+
+impl Track for FixedTrack {
+    fn time_range(&self) -> Option<(f64, f64)> {
+        Some((self.start_utc, self.stop_utc))
+    }
+
+    fn position_at(&self, time: f64) -> Option<(f64, f64)> {
+        (self.start_utc..=self.stop_utc)
+            .contains(&time)
+            .then_some((self.latitude, self.longitude))
+    }
+
+    fn attributes_at(&self, _time: f64) -> TrackAttributes {
+        TrackAttributes::default()
+    }
+
+    fn altitude_at(&self, _time: f64) -> f64 {
+        self.altitude_km
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_track() {
+        let path = std::env::temp_dir().join("satpass_test_csv_track.csv");
+        std::fs::write(
+            &path,
+            "Time, Lat, Lon, Intensity\n\
+             2020-01-01 06:00:00, 11.0, -179.0, 60\n\
+             2020-01-01T00:00:00Z, 10.0, 179.0, 40\n",
+        )
+        .unwrap();
+        let track = CsvTrack::from_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (start, stop) = track.time_range().unwrap();
+        assert_eq!(stop - start, 6.0 * 3600.0);
+        assert_eq!(track.longitude, vec![179.0, 181.0]);
+        let (lat, lon) = track.position_at(start + 3.0 * 3600.0).unwrap();
+        assert!((lat - 10.5).abs() < 1e-9);
+        assert!((lon - 180.0).abs() < 1e-9);
+        let attributes = track.attributes_at(start + 3.0 * 3600.0);
+        assert!((attributes.intensity.unwrap() - 50.0).abs() < 1e-9);
+        assert!(attributes.heading.unwrap() > 0.0 && attributes.heading.unwrap() < 90.0);
+        assert!(track.position_at(stop + 1.0).is_none());
+    }
+
+    #[test]
+    fn test_csv_track_duplicate_time() {
+        let path = std::env::temp_dir().join("satpass_test_csv_track_duplicate.csv");
+        std::fs::write(
+            &path,
+            "time,lat,lon\n\
+             2020-01-01 06:00, 11.0, 150.0\n\
+             2020-01-01 00:00, 10.0, 151.0\n\
+             2020-01-01T06:00:00Z, 11.5, 149.0\n",
+        )
+        .unwrap();
+        let err = CsvTrack::from_file(path.to_str().unwrap()).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.to_string().ends_with("rows 2 and 4 have the same time"), "{}", err);
+    }
+
+    #[test]
+    fn test_bdeck_interpolate() {
+        // An Atlantic storm moving east across Greenwich, 1.0 W to 1.0 E
+        let bdeck = BDeck {
+            storm_id: "AL012020".to_string(),
+            time: vec![0.0, 21600.0],
+            intensity: vec![40.0, 60.0],
+            latitude: vec![30.0, 31.0],
+            longitude: vec![359.0, 1.0],
+        };
+        let (lat, lon, intensity) = bdeck.interpolate(5400.0).unwrap();
+        assert!((lat - 30.25).abs() < 1e-9 && (intensity - 45.0).abs() < 1e-9);
+        assert!((lon - 359.5).abs() < 1e-9, "{}", lon);
+        let (_, lon) = bdeck.position_at(16200.0).unwrap();
+        assert!((lon - 0.5).abs() < 1e-9, "{}", lon);
+        assert!(bdeck.position_at(21601.0).is_none());
+    }
+
+    #[test]
+    fn test_bdeck_from_file() {
+        let full = "WP, 31, 2013110300,   , BEST,   0,  60N, 1600E,  25,   988, TY,  34, NEQ,  100,  100,   80,   90, \
+                    1004,  250,  15,   0,   0,   W,   0,    ,   0,   0,    HAIYAN, D,";
+        let short = "WP, 31, 2013110306,   , BEST,   0,  64S, 1588W,  33,   984, TY,";
+        let path = std::env::temp_dir().join("satpass_test_bdeck.dat");
+        let path_str = path.to_str().unwrap();
+        std::fs::write(&path, format!("{}\n\n{}\n", full, short)).unwrap();
+        let bdeck = BDeck::from_file(path_str).unwrap();
+        assert_eq!(bdeck.storm_id, "WP312013");
+        assert_eq!(bdeck.time, vec![1383436800.0, 1383458400.0]);
+        assert_eq!(bdeck.intensity, vec![25.0, 33.0]);
+        assert!((bdeck.latitude[0] - 6.0).abs() < 1e-4 && (bdeck.latitude[1] + 6.4).abs() < 1e-4);
+        assert!((bdeck.longitude[1] - 201.2).abs() < 1e-4);
+
+        // Short, non-numeric time and non-numeric latitude
+        let bad_lat = short.replace("64S", "6xS");
+        for bad in ["WP, 31, 2013110", "WP, 31, 20131103xx,   , BEST,   0,  60N, 1600E,  25,", &bad_lat] {
+            std::fs::write(&path, format!("{}\n{}\n", full, bad)).unwrap();
+            let err = BDeck::from_file(path_str).err().unwrap();
+            assert_eq!(err.to_string(), format!("{}: bad row 2: {}", path_str, bad));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let midnight = 1577836800.0;
+        assert_eq!(parse_time("2020-01-01"), Some(midnight));
+        assert_eq!(parse_time("2020-01-01 06:30"), Some(midnight + 23400.0));
+        assert_eq!(parse_time("2020-01-01T06:30:00Z"), Some(midnight + 23400.0));
+        assert_eq!(parse_time("2020-01-01T08:30:00+02:00"), Some(midnight + 23400.0));
+        assert_eq!(parse_time("01/01/2020"), None);
+    }
+}